@@ -0,0 +1,214 @@
+use crate::parser::{self, Connector};
+use crate::utils::shell_quote;
+use std::collections::BTreeMap;
+
+/// Alias names mapped to the text that replaces them.
+pub type Aliases = BTreeMap<String, String>;
+
+/// Replaces the first word of each simple command in `line`, after `&&`,
+/// `||` and `|`, with its alias, if it has one. The replacement's own first
+/// word is expanded in turn, except for aliases already being expanded,
+/// and a replacement ending in a blank makes the word after it a candidate
+/// too. Quoted words never match.
+pub fn expand_aliases(line: &str, aliases: &Aliases) -> String {
+    let Ok(list) = parser::split_list(line) else {
+        return line.to_string();
+    };
+
+    let mut changed = false;
+    let mut expanded = String::new();
+    for (connector, pipeline) in list {
+        match connector {
+            Some(Connector::And) => expanded.push_str(" && "),
+            Some(Connector::Or) => expanded.push_str(" || "),
+            None => {}
+        }
+        if pipeline.negated {
+            expanded.push_str("! ");
+        }
+
+        let commands: Vec<String> = pipeline
+            .commands
+            .iter()
+            .map(|command| {
+                let replaced = expand_first_word(command, aliases, &mut Vec::new());
+                changed |= replaced != *command;
+                replaced
+            })
+            .collect();
+        expanded.push_str(&commands.join(" | "));
+    }
+
+    match changed {
+        true => expanded,
+        false => line.to_string(),
+    }
+}
+
+fn expand_first_word(text: &str, aliases: &Aliases, expanding: &mut Vec<String>) -> String {
+    let trimmed = text.trim_start();
+    let leading = &text[..text.len() - trimmed.len()];
+    let (word, rest) = split_first_word(trimmed);
+
+    let Some(value) = aliases.get(word) else {
+        return text.to_string();
+    };
+    if expanding.iter().any(|name| name == word) {
+        return text.to_string();
+    }
+
+    expanding.push(word.to_string());
+    let replacement = expand_first_word(value, aliases, expanding);
+    expanding.pop();
+
+    let rest = if value.ends_with([' ', '\t']) {
+        expand_first_word(rest, aliases, &mut Vec::new())
+    } else {
+        rest.to_string()
+    };
+
+    format!("{leading}{replacement}{rest}")
+}
+
+/// Splits off the first word, which ends at an unquoted blank or operator.
+fn split_first_word(text: &str) -> (&str, &str) {
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut to_escape = false;
+    for (index, c) in text.char_indices() {
+        if to_escape {
+            to_escape = false;
+            continue;
+        }
+        match c {
+            '\\' if !in_single_quote => to_escape = true,
+            '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+            '"' if !in_single_quote => in_double_quote = !in_double_quote,
+            _ if in_single_quote || in_double_quote => {}
+            _ if c.is_whitespace() || matches!(c, ';' | '|' | '&' | '<' | '>' | '(' | ')') => {
+                return text.split_at(index);
+            }
+            _ => {}
+        }
+    }
+
+    (text, "")
+}
+
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(|c: char| {
+            c.is_whitespace() || matches!(c, '/' | '$' | '`' | '=' | '\'' | '"' | '\\')
+        })
+}
+
+/// Implements `alias [-p] [name[=value] ...]`.
+pub fn alias(args: &[String], aliases: &mut Aliases) -> Result<String, String> {
+    let describe = |name: &str, value: &str| format!("alias {}={}\n", name, shell_quote(value));
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "-p").collect();
+
+    if args.is_empty() {
+        return Ok(aliases
+            .iter()
+            .map(|(name, value)| describe(name, value))
+            .collect());
+    }
+
+    let mut output = String::new();
+    let mut errors = String::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, _)) if !is_valid_alias_name(name) => {
+                errors.push_str(&format!("alias: `{name}': invalid alias name\n"));
+            }
+            Some((name, value)) => {
+                aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match aliases.get(arg.as_str()) {
+                Some(value) => output.push_str(&describe(arg, value)),
+                None => errors.push_str(&format!("alias: {arg}: not found\n")),
+            },
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Implements `unalias [-a] name ...`.
+pub fn unalias(args: &[String], aliases: &mut Aliases) -> Result<(), String> {
+    if args.first().is_some_and(|arg| arg == "-a") {
+        aliases.clear();
+        return Ok(());
+    }
+    if args.is_empty() {
+        return Err("unalias: usage: unalias [-a] name [name ...]\n".to_string());
+    }
+
+    let mut errors = String::new();
+    for name in args {
+        if aliases.remove(name).is_none() {
+            errors.push_str(&format!("unalias: {name}: not found\n"));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases_of(pairs: &[(&str, &str)]) -> Aliases {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_expand_aliases_chains_and_stops_recursion() {
+        let aliases = aliases_of(&[("ll", "ls -la"), ("ls", "ls --color"), ("l", "ll")]);
+
+        assert_eq!(expand_aliases("l /tmp", &aliases), "ls --color -la /tmp");
+        assert_eq!(expand_aliases("'ll' /tmp", &aliases), "'ll' /tmp");
+        assert_eq!(expand_aliases("echo ll", &aliases), "echo ll");
+    }
+
+    #[test]
+    fn test_expand_aliases_in_each_command_of_a_list() {
+        let aliases = aliases_of(&[("ll", "echo LL")]);
+
+        assert_eq!(expand_aliases("true && ll y", &aliases), "true && echo LL y");
+        assert_eq!(expand_aliases("false || ll", &aliases), "false || echo LL");
+        assert_eq!(expand_aliases("echo a | ll z", &aliases), "echo a | echo LL z");
+        assert_eq!(expand_aliases("! ll | ll", &aliases), "! echo LL | echo LL");
+        assert_eq!(expand_aliases("[[ a || ll ]]", &aliases), "[[ a || ll ]]");
+        assert_eq!(expand_aliases("echo 'a | ll'", &aliases), "echo 'a | ll'");
+    }
+
+    #[test]
+    fn test_expand_aliases_after_trailing_space() {
+        let aliases = aliases_of(&[("sudo", "sudo "), ("ll", "ls -la")]);
+
+        let result = expand_aliases("sudo ll>out", &aliases);
+        assert_eq!(result, "sudo  ls -la>out");
+    }
+
+    #[test]
+    fn test_alias_lists_and_defines() {
+        let mut aliases = Aliases::new();
+        let args = vec!["ll=ls -la".to_string(), "g=git".to_string()];
+        alias(&args, &mut aliases).unwrap();
+
+        let result = alias(&[], &mut aliases);
+        assert_eq!(result, Ok("alias g='git'\nalias ll='ls -la'\n".to_string()));
+    }
+}
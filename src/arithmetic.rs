@@ -0,0 +1,333 @@
+use crate::variables::Variables;
+
+/// How deeply variables whose values are themselves expressions may nest.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+const OPERATORS: &[&str] = &[
+    "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "(", ")", "?", ":", "[", "]",
+];
+
+/// Evaluates an arithmetic expression as `declare -i` variables and array
+/// subscripts do. Names stand for the value of the variable, itself read
+/// as an expression, and unset or empty ones are zero.
+pub fn evaluate(expression: &str, variables: &Variables) -> Result<i64, String> {
+    evaluate_nested(expression, variables, 0)
+}
+
+fn evaluate_nested(expression: &str, variables: &Variables, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("{expression}: expression recursion level exceeded"));
+    }
+
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser {
+        expression,
+        tokens,
+        position: 0,
+        variables,
+        depth,
+    };
+    let value = parser.ternary()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(value),
+        Some(_) => Err(parser.syntax_error()),
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().unwrap();
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '#' && c != '@' && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Number(parse_number(&rest[..end])?));
+            rest = &rest[end..];
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            let end = rest
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let operator = OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(**operator))
+                .ok_or_else(|| {
+                    format!(
+                        "{expression}: syntax error: operand expected (error token is \"{rest}\")"
+                    )
+                })?;
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// Parses a decimal, octal (`017`), hex (`0x1f`) or `base#digits` constant.
+fn parse_number(text: &str) -> Result<i64, String> {
+    let invalid = || format!("{text}: value too great for base (error token is \"{text}\")");
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base = base
+            .parse::<u32>()
+            .ok()
+            .filter(|base| (2..=64).contains(base));
+        (
+            base.ok_or_else(|| format!("{text}: invalid arithmetic base"))?,
+            digits,
+        )
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    variables: &'a Variables,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn syntax_error(&self) -> String {
+        let rest: Vec<String> = self.tokens[self.position.min(self.tokens.len())..]
+            .iter()
+            .map(|token| match token {
+                Token::Number(number) => number.to_string(),
+                Token::Name(name) => name.clone(),
+                Token::Operator(operator) => operator.to_string(),
+            })
+            .collect();
+        match rest.is_empty() {
+            true => format!("{}: syntax error: operand expected", self.expression),
+            false => format!(
+                "{}: syntax error in expression (error token is \"{}\")",
+                self.expression,
+                rest.join(" ")
+            ),
+        }
+    }
+
+    fn accept(&mut self, operator: &str) -> bool {
+        let found = matches!(self.tokens.get(self.position), Some(Token::Operator(found)) if *found == operator);
+        if found {
+            self.position += 1;
+        }
+
+        found
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), String> {
+        match self.accept(operator) {
+            true => Ok(()),
+            false => Err(self.syntax_error()),
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let condition = self.binary(0)?;
+        if !self.accept("?") {
+            return Ok(condition);
+        }
+
+        let if_true = self.ternary()?;
+        self.expect(":")?;
+        let if_false = self.ternary()?;
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+
+    /// Parses binary operators by precedence, lowest first.
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        const LEVELS: &[&[&str]] = &[
+            &["||"],
+            &["&&"],
+            &["|"],
+            &["^"],
+            &["&"],
+            &["==", "!="],
+            &["<", "<=", ">", ">="],
+            &["<<", ">>"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        let Some(operators) = LEVELS.get(level) else {
+            return self.power();
+        };
+
+        let mut left = self.binary(level + 1)?;
+        while let Some(operator) = operators.iter().find(|operator| {
+            matches!(self.tokens.get(self.position), Some(Token::Operator(found)) if found == *operator)
+        }) {
+            self.position += 1;
+            let right = self.binary(level + 1)?;
+            left = match *operator {
+                "||" => i64::from(left != 0 || right != 0),
+                "&&" => i64::from(left != 0 && right != 0),
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "==" => i64::from(left == right),
+                "!=" => i64::from(left != right),
+                "<" => i64::from(left < right),
+                "<=" => i64::from(left <= right),
+                ">" => i64::from(left > right),
+                ">=" => i64::from(left >= right),
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "*" => left.wrapping_mul(right),
+                _ if right == 0 => {
+                    return Err(format!("{}: division by 0", self.expression));
+                }
+                "/" => left.wrapping_div(right),
+                _ => left.wrapping_rem(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn power(&mut self) -> Result<i64, String> {
+        let base = self.unary()?;
+        if !self.accept("**") {
+            return Ok(base);
+        }
+
+        let exponent = self.power()?;
+        if exponent < 0 {
+            return Err(format!("{}: exponent less than 0", self.expression));
+        }
+        Ok(base.wrapping_pow(exponent.min(u32::MAX as i64) as u32))
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.accept("-") {
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        if self.accept("+") {
+            return self.unary();
+        }
+        if self.accept("!") {
+            return Ok(i64::from(self.unary()? == 0));
+        }
+        if self.accept("~") {
+            return Ok(!self.unary()?);
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        match self.tokens.get(self.position).cloned() {
+            Some(Token::Number(number)) => {
+                self.position += 1;
+                Ok(number)
+            }
+            Some(Token::Name(name)) => {
+                self.position += 1;
+                let value = match self.accept("[") {
+                    true => {
+                        let index = self.ternary()?;
+                        self.expect("]")?;
+                        self.variables
+                            .element(&name, &index.to_string())
+                            .unwrap_or_default()
+                    }
+                    false => self.variables.get(&name).unwrap_or_default().to_string(),
+                };
+                self.variable_value(&value)
+            }
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let value = self.ternary()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            _ => Err(self.syntax_error()),
+        }
+    }
+
+    fn variable_value(&self, value: &str) -> Result<i64, String> {
+        evaluate_nested(value, self.variables, self.depth + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_precedence_and_bases() {
+        let variables = Variables::default();
+
+        assert_eq!(evaluate("2 + 3 * 4 ** 2", &variables), Ok(50));
+        assert_eq!(evaluate("(2 + 3) * -4 % 7", &variables), Ok(-6));
+        assert_eq!(evaluate("0x1f + 017 + 2#101", &variables), Ok(51));
+        assert_eq!(evaluate("1 < 2 && !0 ? 10 : 20", &variables), Ok(10));
+        assert_eq!(evaluate("", &variables), Ok(0));
+    }
+
+    #[test]
+    fn test_evaluate_reads_variables_as_expressions() {
+        let mut variables = Variables::default();
+        variables.set("a", "b + 1");
+        variables.set("b", "4");
+
+        assert_eq!(evaluate("a * 2", &variables), Ok(10));
+        assert_eq!(evaluate("unset_name + 1", &variables), Ok(1));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        let variables = Variables::default();
+
+        assert_eq!(
+            evaluate("1 / 0", &variables),
+            Err("1 / 0: division by 0".to_string())
+        );
+        assert!(evaluate("2 +", &variables).is_err());
+        assert!(evaluate("09", &variables).is_err());
+    }
+}
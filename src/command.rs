@@ -1,6 +1,29 @@
-use crate::utils::{ensure_file_exists_for_redirection, write_or_append_to_file};
-use std::io::Write;
-use std::process::exit;
+use crate::shell::Shell;
+use crate::alias;
+use crate::completion;
+use crate::conditional;
+use crate::conditional::ConditionalWord;
+use crate::declare::{self, Operand};
+use crate::dirs;
+use crate::echo;
+use crate::exec;
+use crate::history;
+use crate::history::HistoryConfig;
+use crate::signals;
+use crate::options;
+use crate::parser::Token;
+use crate::printf;
+use crate::read;
+use crate::trap;
+use crate::variables::Assignment;
+use crate::utils::{
+    ensure_file_exists_for_redirection, error_message, open_redirection_file, with_redirection,
+    write_or_append_to_file,
+};
+use std::io;
+use std::io::ErrorKind;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::ExitStatus;
 use std::{env, fs};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -16,10 +39,16 @@ pub enum OutputChannel {
     Stderr,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum RedirectionKind {
     Redirect,
+    /// `>` under `set -o noclobber`, which refuses to overwrite an existing
+    /// regular file.
+    NoClobber,
     Append,
+    /// `>&N`, which writes wherever the shell's descriptor N does. `file`
+    /// holds N.
+    Duplicate,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -29,7 +58,7 @@ pub enum Command {
         redirection: Option<Redirection>,
     },
     Exit {
-        _arg: i32,
+        arg: Option<String>,
     },
     Type {
         arg: String,
@@ -41,44 +70,208 @@ pub enum Command {
         redirection: Option<Redirection>,
     },
     Pwd {
+        args: Vec<String>,
         redirection: Option<Redirection>,
     },
     Cd {
-        arg: String,
+        args: Vec<String>,
+        redirection: Option<Redirection>,
     },
     Cat {
         args: Vec<String>,
         redirection: Option<Redirection>,
     },
+    Trap {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Set {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Shift {
+        arg: Option<String>,
+    },
+    Assign {
+        assignments: Vec<Assignment>,
+    },
+    DefineFunction {
+        name: String,
+        body: String,
+    },
+    Function {
+        name: String,
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Source {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Return {
+        arg: Option<String>,
+    },
+    History {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Complete {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Compgen {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Alias {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Unalias {
+        args: Vec<String>,
+    },
+    Pushd {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Popd {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Dirs {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Printf {
+        args: Vec<String>,
+        redirection: Option<Redirection>,
+    },
+    Read {
+        args: Vec<String>,
+    },
+    Exec {
+        args: Vec<Token>,
+    },
+    Declare {
+        options: Vec<String>,
+        operands: Vec<Operand>,
+        redirection: Option<Redirection>,
+    },
+    Test {
+        name: String,
+        args: Vec<String>,
+    },
+    Conditional {
+        words: Vec<ConditionalWord>,
+    },
+    /// A command that can't run because its redirection failed.
+    RedirectionError {
+        message: String,
+    },
 }
 
+/// The commands the shell runs itself, which completion offers too.
+pub const BUILTINS: &[&str] = &[
+    "echo", "exit", "type", "pwd", "cd", "trap", "set", "shift", "source", ".", "return",
+    "history", "complete", "compgen", "alias", "unalias", "pushd", "popd", "dirs", "test", "[",
+    "printf", "read", "declare", "typeset", "exec",
+];
+
 impl Command {
     pub fn is_builtin(arg: &str) -> bool {
-        matches!(arg, "echo" | "exit" | "type" | "pwd" | "cd")
+        BUILTINS.contains(&arg)
+    }
+
+    /// Finds the file `source` reads: names with a slash are used as is,
+    /// others are looked up in `PATH` and then the current directory.
+    fn find_sourced_file(name: &str) -> Option<String> {
+        if name.contains('/') {
+            return Some(name.to_string());
+        }
+
+        let path = env::var("PATH").unwrap_or_default();
+        path.split(':')
+            .map(|directory| format!("{directory}/{name}"))
+            .find(|candidate| fs::metadata(candidate).is_ok_and(|metadata| metadata.is_file()))
+            .or_else(|| fs::metadata(name).is_ok().then(|| name.to_string()))
     }
 
     pub fn arg_check_in_path(arg: &str) -> Result<String, String> {
-        let path = env::var("PATH").unwrap();
-
-        let directories = path.split(":").collect::<Vec<_>>();
-        for directory in directories {
-            match fs::read_dir(directory) {
-                Ok(entries) => {
-                    for entry in entries {
-                        if let Ok(entry) = entry {
-                            if entry.file_name() == arg {
-                                return Ok(entry.path().display().to_string());
-                            }
-                        }
-                    }
-                }
-                Err(e) => {
-                    return Err(e.to_string());
+        Command::search_path(|entry| {
+            (entry.file_name() == arg).then(|| entry.path().display().to_string())
+        })
+        .ok_or_else(|| format!("{} not found", arg))
+    }
+
+    /// Visits the entries of each `PATH` directory in order until `visit`
+    /// returns something. Directories that can't be read are skipped.
+    pub fn search_path<T>(mut visit: impl FnMut(&fs::DirEntry) -> Option<T>) -> Option<T> {
+        let path = env::var("PATH").unwrap_or_default();
+
+        for directory in path.split(':') {
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if let Some(found) = visit(&entry) {
+                    return Some(found);
                 }
             }
         }
 
-        Err(format!("{} not found", arg))
+        None
+    }
+
+    /// Points the child's output at the redirection target and makes it
+    /// start with default signal dispositions.
+    fn redirect_child(
+        child: &mut std::process::Command,
+        redirection: &Option<Redirection>,
+    ) -> io::Result<()> {
+        if let Some(redirection) = redirection {
+            let file = open_redirection_file(redirection)?;
+            match redirection.channel {
+                OutputChannel::Stdout => child.stdout(file),
+                OutputChannel::Stderr => child.stderr(file),
+            };
+        }
+        unsafe {
+            child.pre_exec(signals::restore_default_signals);
+        }
+
+        Ok(())
+    }
+
+    /// Converts a child's wait status into a shell exit status, reporting
+    /// fatal signals the way other shells do.
+    fn status_code(status: ExitStatus) -> i32 {
+        let (code, report) = Command::describe_status(status);
+        if let Some(report) = report {
+            eprintln!("{report}");
+        }
+
+        code
+    }
+
+    /// The shell exit status for a child's wait status, and the line to
+    /// report if a signal killed it. A child killed by signal N yields
+    /// 128 + N. Interrupts only get a newline, and broken pipes nothing.
+    fn describe_status(status: ExitStatus) -> (i32, Option<String>) {
+        if let Some(code) = status.code() {
+            return (code, None);
+        }
+
+        let signal = status.signal().unwrap_or(0);
+        let report = match signal {
+            libc::SIGINT => Some(String::new()),
+            libc::SIGPIPE => None,
+            _ if status.core_dumped() => {
+                Some(format!("{} (core dumped)", signals::signal_description(signal)))
+            }
+            _ => Some(signals::signal_description(signal)),
+        };
+
+        (128 + signal, report)
     }
 }
 
@@ -88,9 +281,21 @@ pub struct CommandOutput {
 }
 
 impl CommandOutput {
+    /// Writes a builtin's output, or its error message, and returns its
+    /// exit status.
+    pub fn report(result: Result<String, String>, redirection: &Option<Redirection>) -> i32 {
+        let (message, channel, status) = match result {
+            Ok(output) => (output, OutputChannel::Stdout, 0),
+            Err(message) => (message, OutputChannel::Stderr, 1),
+        };
+        CommandOutput { message, channel }.write(redirection);
+
+        status
+    }
+
     pub fn write(&self, redirection: &Option<Redirection>) {
         if redirection.is_none() {
-            print!("{}", self.message);
+            self.print();
             return;
         }
 
@@ -101,34 +306,61 @@ impl CommandOutput {
         }
 
         ensure_file_exists_for_redirection(redirection);
-        print!("{}", self.message);
+        self.print();
+    }
+
+    fn print(&self) {
+        match self.channel {
+            OutputChannel::Stdout => print!("{}", self.message),
+            OutputChannel::Stderr => eprint!("{}", self.message),
+        }
     }
 }
 
 pub trait Executable {
-    fn execute(&self);
+    fn execute(&self, shell: &mut Shell) -> i32;
 }
 
 impl Executable for Command {
-    fn execute(&self) {
+    fn execute(&self, shell: &mut Shell) -> i32 {
         match self {
             Command::Echo { args, redirection } => {
                 CommandOutput {
-                    message: format!("{}\n", args.join(" ")),
+                    message: echo::echo(args, shell.options.xpg_echo),
                     channel: OutputChannel::Stdout,
                 }
                 .write(redirection);
+                0
             }
-            Command::Exit { _arg: _ } => {
-                exit(0);
+            Command::Exit { arg } => {
+                let status = match arg {
+                    None => shell.last_status,
+                    Some(arg) => match arg.parse::<i64>() {
+                        Ok(status) => (status & 0xff) as i32,
+                        Err(_) => {
+                            eprintln!("exit: {arg}: numeric argument required");
+                            2
+                        }
+                    },
+                };
+
+                shell.exit(status);
             }
             Command::Type { arg, redirection } => {
-                let output = if Command::is_builtin(arg) {
-                    format!("{arg} is a shell builtin\n")
+                let (output, status) = if let Some(value) = shell.aliases.get(arg) {
+                    (format!("{arg} is aliased to `{value}'\n"), 0)
+                } else if let Some(body) = shell.functions.get(arg) {
+                    let body: String = body
+                        .lines()
+                        .map(|line| format!("    {}\n", line.trim()))
+                        .collect();
+                    (format!("{arg} is a function\n{arg} () \n{{\n{body}}}\n"), 0)
+                } else if Command::is_builtin(arg) {
+                    (format!("{arg} is a shell builtin\n"), 0)
                 } else if let Ok(path) = Command::arg_check_in_path(arg) {
-                    format!("{arg} is {path}\n")
+                    (format!("{arg} is {path}\n"), 0)
                 } else {
-                    format!("{arg}: not found\n")
+                    (format!("{arg}: not found\n"), 1)
                 };
 
                 CommandOutput {
@@ -136,53 +368,71 @@ impl Executable for Command {
                     channel: OutputChannel::Stdout,
                 }
                 .write(redirection);
+                status
             }
             Command::External {
                 name,
                 args,
                 redirection,
             } => {
-                Command::arg_check_in_path(name)
-                    .map(|_path| {
-                        let output = std::process::Command::new(name)
-                            .args(args.clone())
-                            .output()
-                            .unwrap();
-
-                        if output.status.success() {
-                            CommandOutput {
-                                message: format!("{}", String::from_utf8_lossy(&output.stdout)),
-                                channel: OutputChannel::Stdout,
-                            }
-                            .write(redirection);
-                        } else {
-                            CommandOutput {
-                                message: format!("{}", String::from_utf8_lossy(&output.stderr)),
-                                channel: OutputChannel::Stderr,
-                            }
-                            .write(redirection);
+                if !name.contains('/') && Command::arg_check_in_path(name).is_err() {
+                    CommandOutput {
+                        message: format!("{}: command not found\n", name),
+                        channel: OutputChannel::Stderr,
+                    }
+                    .write(redirection);
+                    return 127;
+                }
+
+                let mut child = std::process::Command::new(name);
+                child.args(args);
+                if let Err(e) = Command::redirect_child(&mut child, redirection) {
+                    eprintln!("{}: {}", redirection.as_ref().unwrap().file, error_message(&e));
+                    return 1;
+                }
+
+                let status = match child.status() {
+                    Err(e) if e.raw_os_error() == Some(libc::ENOEXEC) => {
+                        // No shebang line, so run the file as one of our scripts.
+                        let mut script = std::process::Command::new(
+                            env::current_exe().unwrap_or_else(|_| "shell_shell".into()),
+                        );
+                        script.arg(name).args(args);
+                        if let Err(e) = Command::redirect_child(&mut script, redirection) {
+                            eprintln!("{}: {}", name, error_message(&e));
+                            return 1;
                         }
-                    })
-                    .unwrap_or_else(|_| {
-                        CommandOutput {
-                            message: format!("{}: command not found\n", name),
-                            channel: OutputChannel::Stderr,
+                        script.status()
+                    }
+                    status => status,
+                };
+
+                match status {
+                    Ok(status) => Command::status_code(status),
+                    Err(e) => {
+                        eprintln!("{}: {}", name, error_message(&e));
+                        if e.kind() == ErrorKind::NotFound {
+                            127
+                        } else {
+                            126
                         }
-                        .write(redirection);
-                    });
-            }
-            Command::Pwd { redirection } => {
-                CommandOutput {
-                    message: format!("{}\n", env::current_dir().unwrap().display()),
-                    channel: OutputChannel::Stdout,
+                    }
                 }
-                .write(redirection);
             }
-            Command::Cd { arg } => {
-                let result = env::set_current_dir(&arg);
-                if let Err(_e) = result {
-                    println!("cd: {arg}: No such file or directory");
-                }
+            Command::Pwd { args, redirection } => {
+                CommandOutput::report(dirs::pwd(args, shell), redirection)
+            }
+            Command::Cd { args, redirection } => {
+                CommandOutput::report(dirs::cd(args, shell), redirection)
+            }
+            Command::Pushd { args, redirection } => {
+                CommandOutput::report(dirs::pushd(args, shell), redirection)
+            }
+            Command::Popd { args, redirection } => {
+                CommandOutput::report(dirs::popd(args, shell), redirection)
+            }
+            Command::Dirs { args, redirection } => {
+                CommandOutput::report(dirs::dirs(args, shell), redirection)
             }
             Command::Cat { args, redirection } => {
                 let mut output = String::new();
@@ -195,28 +445,232 @@ impl Executable for Command {
                     }
                 }
 
+                let status = if error.is_empty() { 0 } else { 1 };
                 if redirection.is_none() {
                     if !error.is_empty() {
                         print!("{}", error);
                     } else {
                         print!("{}", output);
                     }
-                    return;
+                    return status;
                 }
 
                 let redirection = redirection.as_ref().unwrap();
 
-                ensure_file_exists_for_redirection(redirection);
                 if !error.is_empty() && redirection.channel == OutputChannel::Stderr {
-                    write_or_append_to_file(&error, &redirection);
+                    write_or_append_to_file(&error, redirection);
                     print!("{}", output);
-                    return;
+                    return status;
                 }
                 if !output.is_empty() && redirection.channel == OutputChannel::Stdout {
-                    write_or_append_to_file(&output, &redirection);
+                    write_or_append_to_file(&output, redirection);
                     print!("{}", error);
+                    return status;
+                }
+                ensure_file_exists_for_redirection(redirection);
+                status
+            }
+            Command::Trap { args, redirection } => {
+                CommandOutput::report(trap::trap(args, &mut shell.traps), redirection)
+            }
+            Command::History { args, redirection } => {
+                let config = HistoryConfig::from_variables(&shell.variables);
+                CommandOutput::report(
+                    history::history(args, &mut shell.history, &config),
+                    redirection,
+                )
+            }
+            Command::Complete { args, redirection } => {
+                CommandOutput::report(completion::complete_builtin(args, shell), redirection)
+            }
+            Command::Compgen { args, redirection } => {
+                CommandOutput::report(completion::compgen(args, shell), redirection)
+            }
+            Command::Alias { args, redirection } => {
+                CommandOutput::report(alias::alias(args, &mut shell.aliases), redirection)
+            }
+            Command::Printf { args, redirection } => {
+                let (output, errors) = printf::printf(args, &mut shell.variables);
+                let status = i32::from(!errors.is_empty());
+                let mut outputs = vec![CommandOutput {
+                    message: output,
+                    channel: OutputChannel::Stdout,
+                }];
+                if !errors.is_empty() {
+                    outputs.push(CommandOutput {
+                        message: errors,
+                        channel: OutputChannel::Stderr,
+                    });
+                }
+
+                // Under `set -o noclobber` only the first write may create the
+                // file, so the one that goes to it comes first.
+                outputs.sort_by_key(|output| {
+                    redirection
+                        .as_ref()
+                        .is_some_and(|redirection| redirection.channel != output.channel)
+                });
+                for output in outputs {
+                    output.write(redirection);
+                }
+                status
+            }
+            Command::Declare {
+                options,
+                operands,
+                redirection,
+            } => {
+                CommandOutput::report(
+                    declare::declare(options, operands, &mut shell.variables),
+                    redirection,
+                )
+            }
+            Command::Read { args } => match read::read(args, shell) {
+                Ok(status) => status,
+                Err(message) => {
+                    eprint!("{message}");
+                    2
+                }
+            },
+            Command::Exec { args } => match exec::exec(args, shell) {
+                Ok(status) => status,
+                Err(message) => {
+                    eprintln!("{message}");
+                    1
+                }
+            },
+            Command::Test { name, args } => match conditional::test(name, args, shell) {
+                Ok(result) => i32::from(!result),
+                Err(message) => {
+                    eprintln!("{name}: {message}");
+                    2
+                }
+            },
+            Command::Conditional { words } => match conditional::conditional(words, shell) {
+                Ok(result) => i32::from(!result),
+                Err(message) => {
+                    eprintln!("{message}");
+                    2
+                }
+            },
+            Command::RedirectionError { message } => {
+                eprintln!("{message}");
+                1
+            }
+            Command::Unalias { args } => match alias::unalias(args, &mut shell.aliases) {
+                Ok(()) => 0,
+                Err(message) => {
+                    eprint!("{message}");
+                    1
+                }
+            },
+            Command::Set { args, redirection } => {
+                CommandOutput::report(
+                    options::set(args, &mut shell.options, &mut shell.positional),
+                    redirection,
+                )
+            }
+            Command::Shift { arg } => {
+                let count = match arg.as_deref().map(str::parse::<usize>) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => {
+                        eprintln!("shift: {}: numeric argument required", arg.as_ref().unwrap());
+                        return 1;
+                    }
+                };
+                if count > shell.positional.len() {
+                    eprintln!("shift: {count}: shift count out of range");
+                    return 1;
+                }
+
+                shell.positional.drain(..count);
+                0
+            }
+            Command::Assign { assignments } => {
+                for assignment in assignments {
+                    if let Err(message) = shell.variables.assign(assignment) {
+                        eprintln!("{message}");
+                        return 1;
+                    }
                 }
+                0
+            }
+            Command::DefineFunction { name, body } => {
+                shell.functions.insert(name.clone(), body.clone());
+                0
+            }
+            Command::Function {
+                name,
+                args,
+                redirection,
+            } => with_redirection(redirection, || shell.call_function(name, args))
+                .unwrap_or_else(|e| {
+                    eprintln!("{}: {}", redirection.as_ref().unwrap().file, error_message(&e));
+                    1
+                }),
+            Command::Source { args, redirection } => {
+                let Some(name) = args.first() else {
+                    eprintln!("source: filename argument required");
+                    return 2;
+                };
+                let Some(path) = Command::find_sourced_file(name) else {
+                    eprintln!("source: {name}: file not found");
+                    return 1;
+                };
+                let source = match fs::read_to_string(&path) {
+                    Ok(source) => source,
+                    Err(e) => {
+                        eprintln!("source: {}: {}", name, error_message(&e));
+                        return 1;
+                    }
+                };
+
+                with_redirection(redirection, || shell.source(&source, &args[1..]))
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}: {}", redirection.as_ref().unwrap().file, error_message(&e));
+                        1
+                    })
+            }
+            Command::Return { arg } => {
+                if !shell.can_return() {
+                    eprintln!("return: can only `return' from a function or sourced script");
+                    return 1;
+                }
+                let status = match arg {
+                    None => shell.last_status,
+                    Some(arg) => match arg.parse::<i64>() {
+                        Ok(status) => (status & 0xff) as i32,
+                        Err(_) => {
+                            eprintln!("return: {arg}: numeric argument required");
+                            2
+                        }
+                    },
+                };
+
+                shell.returning = true;
+                status
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_status_of_exited_and_killed_children() {
+        let status = |raw: i32| Command::describe_status(ExitStatus::from_raw(raw));
+
+        assert_eq!(status(3 << 8), (3, None));
+        assert_eq!(status(libc::SIGSEGV), (139, Some("Segmentation fault".to_string())));
+        assert_eq!(
+            status(libc::SIGABRT | 0x80),
+            (134, Some("Aborted (core dumped)".to_string()))
+        );
+        assert_eq!(status(libc::SIGINT), (130, Some(String::new())));
+        assert_eq!(status(libc::SIGPIPE), (141, None));
+        assert_eq!(status(libc::SIGKILL), (137, Some("Killed".to_string())));
+    }
+}
@@ -0,0 +1,597 @@
+use crate::command::{Command, BUILTINS};
+use crate::expansion;
+use crate::shell::Shell;
+use crate::utils::{expand_home_path, shell_quote};
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+/// Characters that have to be escaped when a candidate is inserted.
+const SPECIAL_CHARS: &str = " \t\\'\"$`&;|()<>*?[]#!";
+
+/// The word under the cursor and the ways it could be completed.
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    /// Where the word starts, in characters from the start of the line.
+    pub start: usize,
+    /// The word as typed, with backslash escapes removed.
+    pub word: String,
+    pub candidates: Vec<String>,
+}
+
+/// A way of producing candidates, chosen with `-A name` or its short flag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    File,
+    Directory,
+    Command,
+    Builtin,
+    Function,
+    Variable,
+    Alias,
+}
+
+impl Action {
+    const ALL: [(Action, &'static str, Option<char>); 7] = [
+        (Action::File, "file", Some('f')),
+        (Action::Directory, "directory", Some('d')),
+        (Action::Command, "command", Some('c')),
+        (Action::Builtin, "builtin", Some('b')),
+        (Action::Function, "function", None),
+        (Action::Variable, "variable", Some('v')),
+        (Action::Alias, "alias", Some('a')),
+    ];
+
+    fn from_flag(flag: char) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|(_, _, short)| *short == Some(flag))
+            .map(|(action, _, _)| *action)
+    }
+
+    fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|(_, long, _)| *long == name)
+            .map(|(action, _, _)| *action)
+    }
+
+    fn describe(self) -> String {
+        let (_, name, flag) = Action::ALL
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .unwrap();
+        match flag {
+            Some(flag) => format!("-{flag}"),
+            None => format!("-A {name}"),
+        }
+    }
+
+    fn candidates(self, shell: &Shell, word: &str) -> Vec<String> {
+        match self {
+            Action::File => complete_path(word),
+            Action::Directory => complete_path(word)
+                .into_iter()
+                .filter(|candidate| candidate.ends_with('/'))
+                .collect(),
+            Action::Command => complete_command(shell, word),
+            Action::Builtin => with_prefix(BUILTINS.iter().copied(), word),
+            Action::Function => with_prefix(shell.functions.keys().map(String::as_str), word),
+            Action::Variable => with_prefix(shell.variables.names(), word),
+            Action::Alias => with_prefix(shell.aliases.keys().map(String::as_str), word),
+        }
+    }
+}
+
+fn with_prefix<'a>(names: impl Iterator<Item = &'a str>, prefix: &str) -> Vec<String> {
+    names
+        .filter(|name| name.starts_with(prefix))
+        .map(str::to_string)
+        .collect()
+}
+
+/// How to complete the arguments of a command, as registered by `complete`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionSpec {
+    actions: Vec<Action>,
+    /// `-W`: words, split after parameter expansion, to offer.
+    word_list: Option<String>,
+    /// `-F`: a function that leaves its candidates in `COMPREPLY`.
+    function: Option<String>,
+}
+
+impl CompletionSpec {
+    /// The `complete` command that would register this spec again.
+    fn describe(&self, name: &str) -> String {
+        let mut words = vec!["complete".to_string()];
+        words.extend(self.actions.iter().map(|action| action.describe()));
+        if let Some(word_list) = &self.word_list {
+            words.push(format!("-W {}", shell_quote(word_list)));
+        }
+        if let Some(function) = &self.function {
+            words.push(format!("-F {function}"));
+        }
+        words.push(name.to_string());
+
+        words.join(" ")
+    }
+
+    /// Produces the candidates for `word`, the last of `words`.
+    fn generate(&self, shell: &mut Shell, word: &str, words: &[String]) -> Vec<String> {
+        let mut candidates: Vec<String> = self
+            .actions
+            .iter()
+            .flat_map(|action| action.candidates(shell, word))
+            .collect();
+
+        if let Some(word_list) = &self.word_list {
+            let word_list = expansion::expand_parameters(word_list, shell);
+            candidates.extend(with_prefix(word_list.split_whitespace(), word));
+        }
+
+        if let Some(function) = &self.function {
+            candidates.extend(call_completion_function(shell, function, word, words));
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Runs a `-F` function the way bash does: with the command name, the
+/// word and the previous word as arguments, and `COMP_WORDS`, `COMP_CWORD`
+/// and `COMP_LINE` set. `COMPREPLY` holds whitespace-separated candidates.
+fn call_completion_function(
+    shell: &mut Shell,
+    function: &str,
+    word: &str,
+    words: &[String],
+) -> Vec<String> {
+    if !shell.functions.contains_key(function) {
+        return Vec::new();
+    }
+
+    let command = words.first().cloned().unwrap_or_default();
+    let previous = match words.len() {
+        0 | 1 => String::new(),
+        count => words[count - 2].clone(),
+    };
+    let line = words.join(" ");
+    shell.variables.set_array("COMP_WORDS", words);
+    shell
+        .variables
+        .set("COMP_CWORD", &words.len().saturating_sub(1).to_string());
+    shell.variables.set("COMP_LINE", &line);
+    shell.variables.set("COMP_POINT", &line.len().to_string());
+    shell.variables.set_array("COMPREPLY", &[]);
+
+    let status = shell.last_status;
+    shell.call_function(function, &[command, word.to_string(), previous]);
+    shell.last_status = status;
+
+    shell
+        .variables
+        .elements("COMPREPLY")
+        .iter()
+        .flat_map(|reply| reply.split_whitespace())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Completes the word that ends at the end of `line`: command names for
+/// the first word of a command, and otherwise whatever `complete` set up
+/// for the command, falling back to file paths.
+pub fn complete(shell: &mut Shell, line: &str) -> Completion {
+    let (start, word) = current_word(line);
+    let before: String = line.chars().take(start).collect();
+    let command_position = before
+        .trim_end()
+        .chars()
+        .last()
+        .is_none_or(|c| matches!(c, ';' | '|' | '&' | '('));
+
+    let words = command_words(line);
+    let spec = words.first().and_then(|command| {
+        let name = command.rsplit('/').next().unwrap_or(command);
+        shell.completions.get(name).cloned()
+    });
+
+    let candidates = match spec {
+        _ if command_position && !word.contains('/') => complete_command(shell, &word),
+        Some(spec) => spec.generate(shell, &word, &words),
+        None => complete_path(&word),
+    };
+
+    Completion {
+        start,
+        word,
+        candidates,
+    }
+}
+
+/// Finds the start of the last word of `line`, and the word itself.
+fn current_word(line: &str) -> (usize, String) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut start = 0;
+    let mut escaped = false;
+    for (index, &c) in chars.iter().enumerate() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c.is_whitespace() || matches!(c, ';' | '|' | '&' | '(' | '<' | '>') {
+            start = index + 1;
+        }
+    }
+
+    (start, unescape(&chars[start..]))
+}
+
+/// Splits the command being typed at the end of `line` into words, ending
+/// with the (possibly empty) word being completed.
+fn command_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut curr = Vec::new();
+    let mut escaped = false;
+    for c in line.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if matches!(c, ';' | '|' | '&' | '(') {
+            words.clear();
+            curr.clear();
+            continue;
+        } else if c.is_whitespace() {
+            if !curr.is_empty() {
+                words.push(unescape(&curr));
+                curr.clear();
+            }
+            continue;
+        }
+        curr.push(c);
+    }
+    words.push(unescape(&curr));
+
+    words
+}
+
+fn unescape(chars: &[char]) -> String {
+    let mut word = String::new();
+    let mut chars = chars.iter();
+    while let Some(&c) = chars.next() {
+        match c {
+            '\\' => word.extend(chars.next()),
+            _ => word.push(c),
+        }
+    }
+
+    word
+}
+
+/// Escapes a candidate so the tokenizer reads it back as one word.
+pub fn escape(candidate: &str) -> String {
+    let mut escaped = String::new();
+    for c in candidate.chars() {
+        if SPECIAL_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+fn complete_command(shell: &Shell, prefix: &str) -> Vec<String> {
+    let mut names: BTreeSet<String> = BUILTINS
+        .iter()
+        .map(|name| name.to_string())
+        .chain(shell.functions.keys().cloned())
+        .chain(shell.aliases.keys().cloned())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+
+    Command::search_path(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(prefix) && is_executable(&entry.path()) {
+            names.insert(name);
+        }
+        None::<()>
+    });
+
+    names.into_iter().collect()
+}
+
+fn complete_path(word: &str) -> Vec<String> {
+    let (directory, prefix) = match word.rfind('/') {
+        Some(index) => word.split_at(index + 1),
+        None => ("", word),
+    };
+    let lookup = match directory {
+        "" => ".".to_string(),
+        _ => expand_home_path(directory),
+    };
+    let Ok(entries) = fs::read_dir(lookup) else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|metadata| metadata.is_dir());
+            Some(format!(
+                "{directory}{name}{}",
+                if is_dir { "/" } else { "" }
+            ))
+        })
+        .collect();
+    candidates.sort();
+
+    candidates
+}
+
+fn is_executable(path: &std::path::Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Parses the options shared by `complete` and `compgen`, returning the
+/// spec and the remaining operands. `-p` and `-r` are returned as flags.
+fn parse_spec(
+    builtin: &str,
+    args: &[String],
+) -> Result<(CompletionSpec, Vec<char>, Vec<String>), String> {
+    let mut spec = CompletionSpec::default();
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        let Some(options) = arg.strip_prefix('-').filter(|options| !options.is_empty()) else {
+            return Ok((
+                spec,
+                flags,
+                std::iter::once(arg).chain(args).cloned().collect(),
+            ));
+        };
+
+        for option in options.chars() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{builtin}: -{option}: option requires an argument\n"))
+            };
+            match option {
+                'p' | 'r' => flags.push(option),
+                'W' => spec.word_list = Some(value()?),
+                'F' => spec.function = Some(value()?),
+                'A' => {
+                    let name = value()?;
+                    let action = Action::from_name(&name)
+                        .ok_or_else(|| format!("{builtin}: {name}: invalid action name\n"))?;
+                    spec.actions.push(action);
+                }
+                _ => {
+                    let action = Action::from_flag(option)
+                        .ok_or_else(|| format!("{builtin}: -{option}: invalid option\n"))?;
+                    spec.actions.push(action);
+                }
+            }
+        }
+    }
+
+    Ok((spec, flags, args.cloned().collect()))
+}
+
+/// Implements `complete [-p|-r] [name...]` and
+/// `complete [-fdcbv] [-A action] [-W words] [-F function] name...`.
+pub fn complete_builtin(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let (spec, flags, names) = parse_spec("complete", args)?;
+
+    if flags.contains(&'r') {
+        if names.is_empty() {
+            shell.completions.clear();
+        }
+        for name in &names {
+            if shell.completions.remove(name).is_none() {
+                return Err(format!("complete: {name}: no completion specification\n"));
+            }
+        }
+        return Ok(String::new());
+    }
+
+    if flags.contains(&'p') || (names.is_empty() && spec == CompletionSpec::default()) {
+        let mut output = String::new();
+        for (name, spec) in &shell.completions {
+            if names.is_empty() || names.contains(name) {
+                output.push_str(&spec.describe(name));
+                output.push('\n');
+            }
+        }
+        return Ok(output);
+    }
+
+    if names.is_empty() {
+        return Err("complete: a command name is required\n".to_string());
+    }
+    for name in names {
+        shell.completions.insert(name, spec.clone());
+    }
+
+    Ok(String::new())
+}
+
+/// Implements `compgen [options] [word]`, printing the candidates for
+/// `word` one per line. Having none is an error with no message.
+pub fn compgen(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let (spec, _, operands) = parse_spec("compgen", args)?;
+    let word = operands.first().cloned().unwrap_or_default();
+
+    let candidates = spec.generate(shell, &word, std::slice::from_ref(&word));
+    if candidates.is_empty() {
+        return Err(String::new());
+    }
+
+    Ok(candidates
+        .iter()
+        .map(|candidate| format!("{candidate}\n"))
+        .collect())
+}
+
+/// The longest prefix all candidates share.
+pub fn common_prefix(candidates: &[String]) -> String {
+    let Some(first) = candidates.first() else {
+        return String::new();
+    };
+
+    let mut length = first.len();
+    for candidate in &candidates[1..] {
+        length = first
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((index, c), _)| index + c.len_utf8())
+            .min(length);
+    }
+
+    first[..length].to_string()
+}
+
+/// Lays `items` out in columns that fit in `width`, filling each column
+/// from top to bottom like `ls` does.
+pub fn format_columns(items: &[String], width: usize) -> String {
+    let column_width = items
+        .iter()
+        .map(|item| item.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let columns = (width / column_width).max(1);
+    let rows = items.len().div_ceil(columns);
+
+    let mut output = String::new();
+    for row in 0..rows {
+        let line: String = (0..columns)
+            .filter_map(|column| items.get(column * rows + row))
+            .map(|item| format!("{item:<column_width$}"))
+            .collect();
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+
+    output
+}
+
+/// The part of a candidate shown in a list: paths show only their last
+/// component.
+pub fn display_name(candidate: &str) -> &str {
+    let trimmed = candidate.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(index) => &candidate[index + 1..],
+        None => candidate,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_current_word_handles_escaped_spaces() {
+        let input = "cat notes/my\\ fi";
+        let expected = (4, "notes/my fi".to_string());
+
+        let result = current_word(input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_complete_builtins_in_command_position() {
+        let mut shell = Shell::new();
+
+        let result = complete(&mut shell, "echo hi; hist");
+        assert_eq!(result.start, 9);
+        assert!(result.candidates.contains(&"history".to_string()));
+    }
+
+    #[test]
+    fn test_complete_uses_registered_word_list() {
+        let mut shell = Shell::new();
+        let args: Vec<String> = ["-W", "start stop status", "svc"]
+            .map(String::from)
+            .to_vec();
+        complete_builtin(&args, &mut shell).unwrap();
+
+        let result = complete(&mut shell, "svc st");
+        assert_eq!(result.candidates, vec!["start", "status", "stop"]);
+    }
+
+    #[test]
+    fn test_complete_calls_completion_function() {
+        let mut shell = Shell::new();
+        shell.run_source("_git() { COMPREPLY=\"$1-$2-$3\"; }\n");
+        let args: Vec<String> = ["-F", "_git", "git"].map(String::from).to_vec();
+        complete_builtin(&args, &mut shell).unwrap();
+
+        let result = complete(&mut shell, "git commit --am");
+        assert_eq!(result.candidates, vec!["git---am-commit"]);
+        assert_eq!(shell.variables.get("COMP_CWORD"), Some("2"));
+    }
+
+    #[test]
+    fn test_complete_prints_registered_specs() {
+        let mut shell = Shell::new();
+        let args: Vec<String> = ["-d", "-A", "function", "-W", "a b", "mycmd"]
+            .map(String::from)
+            .to_vec();
+        complete_builtin(&args, &mut shell).unwrap();
+
+        let result = complete_builtin(&[], &mut shell);
+        assert_eq!(
+            result,
+            Ok("complete -d -A function -W 'a b' mycmd\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compgen_filters_word_list() {
+        let mut shell = Shell::new();
+        let args: Vec<String> = ["-W", "alpha beta alps", "--", "al"]
+            .map(String::from)
+            .to_vec();
+
+        let result = compgen(&args, &mut shell);
+        assert_eq!(result, Ok("alpha\nalps\n".to_string()));
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let input = vec![
+            "src/main.rs".to_string(),
+            "src/mod.rs".to_string(),
+            "src/m".to_string(),
+        ];
+        let expected = "src/m";
+
+        let result = common_prefix(&input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_format_columns_fills_columns_first() {
+        let input: Vec<String> = ["a", "bb", "c", "dd", "e"].map(String::from).to_vec();
+        let expected = "a   dd\nbb  e\nc\n";
+
+        let result = format_columns(&input, 8);
+        assert_eq!(result, expected);
+    }
+}
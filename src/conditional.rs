@@ -0,0 +1,560 @@
+use crate::expansion::{expand_word_segments, read_substitution};
+use crate::glob;
+use crate::shell::Shell;
+use std::ffi::CString;
+use std::fs;
+use std::mem;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::time::SystemTime;
+
+const UNARY_OPERATORS: &[&str] = &[
+    "-a", "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-O", "-p", "-r",
+    "-s", "-S", "-t", "-u", "-v", "-w", "-x", "-z",
+];
+
+const BINARY_OPERATORS: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// A word of a conditional expression after expansion. The quoted parts of
+/// a `[[ ]]` word match literally when it is used as a pattern or regular
+/// expression, so those forms are kept alongside the text.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConditionalWord {
+    pub text: String,
+    pub pattern: String,
+    pub regex: String,
+    /// Whether the word was written without quotes or expansions, which
+    /// operators and parentheses must be.
+    pub bare: bool,
+}
+
+impl ConditionalWord {
+    /// A `test` argument, which is already expanded and may be an operator.
+    pub fn literal(text: &str) -> Self {
+        ConditionalWord {
+            text: text.to_string(),
+            pattern: text.to_string(),
+            regex: text.to_string(),
+            bare: true,
+        }
+    }
+
+    /// Expands a word of `[[ ]]` without splitting it.
+    pub fn expand(raw: &str, shell: &mut Shell) -> Self {
+        let mut word = ConditionalWord {
+            text: String::new(),
+            pattern: String::new(),
+            regex: String::new(),
+            bare: !raw.contains(['\'', '"', '\\', '$', '`']),
+        };
+        for (text, quoted) in expand_word_segments(raw, shell) {
+            word.text.push_str(&text);
+            if quoted {
+                word.pattern.push_str(&glob::escape(&text));
+                word.regex.push_str(&regex_escape(&text));
+            } else {
+                word.pattern.push_str(&text);
+                word.regex.push_str(&text);
+            }
+        }
+
+        word
+    }
+
+    fn is(&self, operator: &str) -> bool {
+        self.bare && self.text == operator
+    }
+}
+
+/// Splits what follows `[[` into words at unquoted blanks. Parentheses,
+/// `<`, `>`, `&&` and `||` are words of their own, except in the regular
+/// expression after `=~`, where parentheses and `|` are part of the word.
+pub fn split_words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut curr = String::new();
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut in_regex = false;
+    let mut regex_depth = 0;
+
+    let finish = |curr: &mut String, words: &mut Vec<String>, in_regex: &mut bool| {
+        if !curr.is_empty() {
+            *in_regex = curr == "=~";
+            words.push(mem::take(curr));
+        }
+    };
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_single_quote {
+            in_single_quote = c != '\'';
+            curr.push(c);
+            continue;
+        }
+
+        match c {
+            '\\' => {
+                curr.push(c);
+                curr.extend(chars.next());
+            }
+            '\'' if !in_double_quote => {
+                in_single_quote = true;
+                curr.push(c);
+            }
+            '"' => {
+                in_double_quote = !in_double_quote;
+                curr.push(c);
+            }
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                curr.push_str("$(");
+                if let Some(command) = read_substitution(&mut chars) {
+                    curr.push_str(&command);
+                    curr.push(')');
+                }
+            }
+            '`' => {
+                curr.push(c);
+                while let Some(next) = chars.next() {
+                    curr.push(next);
+                    if next == '\\' {
+                        curr.extend(chars.next());
+                    } else if next == '`' {
+                        break;
+                    }
+                }
+            }
+            _ if in_double_quote => curr.push(c),
+            '(' if in_regex => {
+                regex_depth += 1;
+                curr.push(c);
+            }
+            ')' if in_regex && regex_depth > 0 => {
+                regex_depth -= 1;
+                curr.push(c);
+            }
+            '|' if in_regex => curr.push(c),
+            _ if c.is_whitespace() && regex_depth == 0 => {
+                finish(&mut curr, &mut words, &mut in_regex);
+            }
+            _ if c.is_whitespace() => curr.push(c),
+            '(' | ')' | '<' | '>' => {
+                finish(&mut curr, &mut words, &mut in_regex);
+                words.push(c.to_string());
+                in_regex = false;
+            }
+            '&' | '|' if chars.peek() == Some(&c) => {
+                chars.next();
+                finish(&mut curr, &mut words, &mut in_regex);
+                words.push(format!("{c}{c}"));
+                in_regex = false;
+            }
+            _ => curr.push(c),
+        }
+    }
+    finish(&mut curr, &mut words, &mut in_regex);
+
+    words
+}
+
+/// Evaluates `test` or `[` arguments. With up to four arguments the POSIX
+/// rules decide what each one is; longer expressions are parsed with `-a`,
+/// `-o`, `!` and parentheses.
+pub fn test(name: &str, args: &[String], shell: &mut Shell) -> Result<bool, String> {
+    let args = match name {
+        "[" => match args.split_last() {
+            Some((last, rest)) if last == "]" => rest,
+            _ => return Err("missing `]'".to_string()),
+        },
+        _ => args,
+    };
+
+    let words: Vec<ConditionalWord> = args.iter().map(|arg| ConditionalWord::literal(arg)).collect();
+    Evaluator::new(&words, false, shell).evaluate_test()
+}
+
+/// Evaluates the words of `[[ ... ]]`, which end with the closing `]]`.
+/// There is no splitting, `&&` and `||` join expressions, `==` and `!=`
+/// match patterns and `=~` matches an extended regular expression, setting
+/// the `BASH_REMATCH` array to the match and its groups.
+pub fn conditional(words: &[ConditionalWord], shell: &mut Shell) -> Result<bool, String> {
+    let words = match words.split_last() {
+        Some((last, rest)) if last.is("]]") && !rest.is_empty() => rest,
+        _ => return Err("syntax error in conditional expression".to_string()),
+    };
+
+    let mut evaluator = Evaluator::new(words, true, shell);
+    let result = evaluator.or()?;
+    match words.get(evaluator.position) {
+        None => Ok(result),
+        Some(word) => Err(format!(
+            "syntax error in conditional expression near `{}'",
+            word.text
+        )),
+    }
+}
+
+struct Evaluator<'a> {
+    words: &'a [ConditionalWord],
+    position: usize,
+    /// Whether this is `[[ ]]` rather than `test`.
+    extended: bool,
+    shell: &'a mut Shell,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(words: &'a [ConditionalWord], extended: bool, shell: &'a mut Shell) -> Self {
+        Evaluator {
+            words,
+            position: 0,
+            extended,
+            shell,
+        }
+    }
+
+    fn evaluate_test(&mut self) -> Result<bool, String> {
+        let words = self.words;
+        match words.len() {
+            0 => Ok(false),
+            1 => Ok(!words[0].text.is_empty()),
+            2 if words[0].is("!") => Ok(words[1].text.is_empty()),
+            2 if is_unary(&words[0]) => Ok(self.unary(&words[0].text, &words[1].text)),
+            2 => Err(format!("{}: unary operator expected", words[0].text)),
+            3 if words[1].is("-a") => Ok(!words[0].text.is_empty() && !words[2].text.is_empty()),
+            3 if words[1].is("-o") => Ok(!words[0].text.is_empty() || !words[2].text.is_empty()),
+            3 if self.is_binary(&words[1]) => self.binary(&words[1].text, &words[0], &words[2]),
+            3 if words[0].is("!") => Ok(!self.nested(1, 3)?),
+            3 if words[0].is("(") && words[2].is(")") => Ok(!words[1].text.is_empty()),
+            4 if words[0].is("!") => Ok(!self.nested(1, 4)?),
+            4 if words[0].is("(") && words[3].is(")") => self.nested(1, 3),
+            _ => {
+                let result = self.or()?;
+                match words.get(self.position) {
+                    None => Ok(result),
+                    Some(word) => Err(format!("{}: unexpected argument", word.text)),
+                }
+            }
+        }
+    }
+
+    /// Evaluates `words[start..end]` by the `test` rules on its own.
+    fn nested(&mut self, start: usize, end: usize) -> Result<bool, String> {
+        Evaluator::new(&self.words[start..end], false, self.shell).evaluate_test()
+    }
+
+    fn accept(&mut self, operator: &str) -> bool {
+        let found = self
+            .words
+            .get(self.position)
+            .is_some_and(|word| word.is(operator));
+        if found {
+            self.position += 1;
+        }
+
+        found
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let operator = if self.extended { "||" } else { "-o" };
+        let mut result = self.and()?;
+        while self.accept(operator) {
+            let right = self.and()?;
+            result = result || right;
+        }
+
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let operator = if self.extended { "&&" } else { "-a" };
+        let mut result = self.not()?;
+        while self.accept(operator) {
+            let right = self.not()?;
+            result = result && right;
+        }
+
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.accept("!") {
+            return Ok(!self.not()?);
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let words = self.words;
+        let Some(word) = words.get(self.position) else {
+            return Err("argument expected".to_string());
+        };
+
+        if self.accept("(") {
+            let result = self.or()?;
+            if !self.accept(")") {
+                return Err("`)' expected".to_string());
+            }
+            return Ok(result);
+        }
+
+        if let (Some(operator), Some(right)) = (
+            words.get(self.position + 1),
+            words.get(self.position + 2),
+        ) {
+            if self.is_binary(operator) {
+                self.position += 3;
+                return self.binary(&operator.text, word, right);
+            }
+        }
+
+        if let Some(operand) = words.get(self.position + 1) {
+            if is_unary(word) {
+                self.position += 2;
+                return Ok(self.unary(&word.text, &operand.text));
+            }
+        }
+
+        self.position += 1;
+        Ok(!word.text.is_empty())
+    }
+
+    fn is_binary(&self, word: &ConditionalWord) -> bool {
+        word.bare
+            && (BINARY_OPERATORS.contains(&word.text.as_str())
+                || (self.extended && word.text == "=~"))
+    }
+
+    fn unary(&self, operator: &str, operand: &str) -> bool {
+        match operator {
+            "-z" => operand.is_empty(),
+            "-n" => !operand.is_empty(),
+            "-v" => self.shell.variables.get(operand).is_some(),
+            "-t" => operand
+                .parse::<i32>()
+                .is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1),
+            "-L" | "-h" => {
+                fs::symlink_metadata(operand).is_ok_and(|metadata| metadata.file_type().is_symlink())
+            }
+            "-r" => is_accessible(operand, libc::R_OK),
+            "-w" => is_accessible(operand, libc::W_OK),
+            "-x" => is_accessible(operand, libc::X_OK),
+            _ => {
+                let Ok(metadata) = fs::metadata(operand) else {
+                    return false;
+                };
+                let file_type = metadata.file_type();
+                match operator {
+                    "-e" | "-a" => true,
+                    "-f" => file_type.is_file(),
+                    "-d" => file_type.is_dir(),
+                    "-s" => metadata.len() > 0,
+                    "-b" => file_type.is_block_device(),
+                    "-c" => file_type.is_char_device(),
+                    "-p" => file_type.is_fifo(),
+                    "-S" => file_type.is_socket(),
+                    "-u" => metadata.mode() & 0o4000 != 0,
+                    "-g" => metadata.mode() & 0o2000 != 0,
+                    "-k" => metadata.mode() & 0o1000 != 0,
+                    "-O" => metadata.uid() == unsafe { libc::geteuid() },
+                    "-G" => metadata.gid() == unsafe { libc::getegid() },
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: &str,
+        left: &ConditionalWord,
+        right: &ConditionalWord,
+    ) -> Result<bool, String> {
+        match operator {
+            "=" | "==" if self.extended => Ok(glob::matches(&right.pattern, &left.text)),
+            "!=" if self.extended => Ok(!glob::matches(&right.pattern, &left.text)),
+            "=" | "==" => Ok(left.text == right.text),
+            "!=" => Ok(left.text != right.text),
+            "<" => Ok(left.text < right.text),
+            ">" => Ok(left.text > right.text),
+            "=~" => {
+                let groups = regex_match(&right.regex, &left.text)?;
+                self.shell.variables.set_array("BASH_REMATCH", &groups);
+                Ok(!groups.is_empty())
+            }
+            "-nt" => Ok(match (modified(&left.text), modified(&right.text)) {
+                (Some(left), Some(right)) => left > right,
+                (left, right) => left.is_some() && right.is_none(),
+            }),
+            "-ot" => Ok(match (modified(&left.text), modified(&right.text)) {
+                (Some(left), Some(right)) => left < right,
+                (left, right) => left.is_none() && right.is_some(),
+            }),
+            "-ef" => Ok(
+                match (fs::metadata(&left.text), fs::metadata(&right.text)) {
+                    (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+                    _ => false,
+                },
+            ),
+            _ => {
+                let (left, right) = (integer(&left.text)?, integer(&right.text)?);
+                Ok(match operator {
+                    "-eq" => left == right,
+                    "-ne" => left != right,
+                    "-lt" => left < right,
+                    "-le" => left <= right,
+                    "-gt" => left > right,
+                    _ => left >= right,
+                })
+            }
+        }
+    }
+}
+
+fn is_unary(word: &ConditionalWord) -> bool {
+    word.bare && UNARY_OPERATORS.contains(&word.text.as_str())
+}
+
+fn integer(text: &str) -> Result<i64, String> {
+    text.trim()
+        .parse()
+        .map_err(|_| format!("{text}: integer expression expected"))
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn is_accessible(path: &str, mode: libc::c_int) -> bool {
+    CString::new(path).is_ok_and(|path| unsafe { libc::access(path.as_ptr(), mode) } == 0)
+}
+
+/// Escapes the characters that are special in extended regular expressions.
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\.^$*+?()[]{}|".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Matches `text` against the extended regular expression `pattern`,
+/// returning the whole match followed by its groups, or nothing if it
+/// doesn't match. Groups that took no part in the match are empty.
+fn regex_match(pattern: &str, text: &str) -> Result<Vec<String>, String> {
+    let invalid = || format!("{pattern}: invalid regular expression");
+    let c_pattern = CString::new(pattern).map_err(|_| invalid())?;
+    let c_text = CString::new(text).map_err(|_| invalid())?;
+
+    let mut regex: libc::regex_t = unsafe { mem::zeroed() };
+    if unsafe { libc::regcomp(&mut regex, c_pattern.as_ptr(), libc::REG_EXTENDED) } != 0 {
+        return Err(invalid());
+    }
+
+    let mut matches = [libc::regmatch_t { rm_so: -1, rm_eo: -1 }; 10];
+    let status = unsafe {
+        libc::regexec(
+            &regex,
+            c_text.as_ptr(),
+            matches.len(),
+            matches.as_mut_ptr(),
+            0,
+        )
+    };
+    unsafe { libc::regfree(&mut regex) };
+    if status != 0 {
+        return Ok(Vec::new());
+    }
+
+    let count = matches.iter().rposition(|group| group.rm_so >= 0).map_or(0, |last| last + 1);
+    Ok(matches[..count]
+        .iter()
+        .map(|group| match group.rm_so {
+            start if start >= 0 => text[start as usize..group.rm_eo as usize].to_string(),
+            _ => String::new(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn evaluate(text: &str, shell: &mut Shell) -> Result<bool, String> {
+        let words: Vec<ConditionalWord> = split_words(text)
+            .iter()
+            .map(|word| ConditionalWord::expand(word, shell))
+            .collect();
+        conditional(&words, shell)
+    }
+
+    #[test]
+    fn test_test_follows_argument_count_rules() {
+        let mut shell = Shell::new();
+
+        assert_eq!(test("test", &args(""), &mut shell), Ok(false));
+        assert_eq!(test("test", &args("-n"), &mut shell), Ok(true));
+        assert_eq!(test("test", &args("! -z"), &mut shell), Ok(false));
+        assert_eq!(test("[", &args("3 -lt 10 ]"), &mut shell), Ok(true));
+        assert_eq!(test("[", &args("a = b -o ( ! -d / ) ]"), &mut shell), Ok(false));
+        assert_eq!(test("[", &args("a = a -a -d / ]"), &mut shell), Ok(true));
+        assert_eq!(test("[", &args("a = a"), &mut shell), Err("missing `]'".to_string()));
+        assert_eq!(
+            test("test", &args("x -eq 1"), &mut shell),
+            Err("x: integer expression expected".to_string())
+        );
+    }
+
+    #[test]
+    fn test_test_file_operators() {
+        let mut shell = Shell::new();
+
+        assert_eq!(test("test", &args("-d /"), &mut shell), Ok(true));
+        assert_eq!(test("test", &args("-f /"), &mut shell), Ok(false));
+        assert_eq!(test("test", &args("-e /no/such/file"), &mut shell), Ok(false));
+        assert_eq!(test("test", &args("/ -ef /."), &mut shell), Ok(true));
+        assert_eq!(test("test", &args("/ -nt /no/such/file"), &mut shell), Ok(true));
+    }
+
+    #[test]
+    fn test_conditional_does_not_split_and_matches_patterns() {
+        let mut shell = Shell::new();
+        shell.variables.set("FILE", "my notes.txt");
+
+        assert_eq!(evaluate("$FILE == *.txt ]]", &mut shell), Ok(true));
+        assert_eq!(evaluate("$FILE == \"*.txt\" ]]", &mut shell), Ok(false));
+        assert_eq!(evaluate("-n $UNSET_VARIABLE || $FILE != my* ]]", &mut shell), Ok(false));
+        assert_eq!(evaluate("( a < b ) && ! -z $FILE ]]", &mut shell), Ok(true));
+        assert!(evaluate("a == ", &mut shell).is_err());
+    }
+
+    #[test]
+    fn test_conditional_regex_sets_bash_rematch() {
+        let mut shell = Shell::new();
+
+        let result = evaluate("release-1.42 =~ ([0-9]+)\\.([0-9]+)$ ]]", &mut shell);
+        assert_eq!(result, Ok(true));
+        assert_eq!(shell.variables.elements("BASH_REMATCH"), vec!["1.42", "1", "42"]);
+
+        let result = evaluate("a.c =~ \"a.c\" && abc =~ \"a.c\" ]]", &mut shell);
+        assert_eq!(result, Ok(false));
+    }
+
+    #[test]
+    fn test_split_words_separates_operators() {
+        let input = "(-n \"$a b\")&&$x =~ ^(a|b c)$ ]]";
+        let expected = vec!["(", "-n", "\"$a b\"", ")", "&&", "$x", "=~", "^(a|b c)$", "]]"];
+
+        let result = split_words(input);
+        assert_eq!(result, expected);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::utils::quote_if_needed;
+use crate::variables::{is_valid_name, Assignment, Value, Variable, Variables};
+use std::collections::BTreeMap;
+
+/// An operand of `declare`: a bare name or an assignment.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Operand {
+    Name(String),
+    Assignment(Assignment),
+}
+
+impl Operand {
+    fn name(&self) -> &str {
+        match self {
+            Operand::Name(name) => name,
+            Operand::Assignment(assignment) => &assignment.name,
+        }
+    }
+}
+
+/// The attributes an option turns on with `-` or off with `+`.
+#[derive(Debug, Default)]
+struct Attributes {
+    indexed: bool,
+    associative: bool,
+    integer: bool,
+    readonly: bool,
+    exported: bool,
+}
+
+impl Attributes {
+    fn set(&mut self, flag: char) -> bool {
+        match flag {
+            'a' => self.indexed = true,
+            'A' => self.associative = true,
+            'i' => self.integer = true,
+            'r' => self.readonly = true,
+            'x' => self.exported = true,
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn any(&self) -> bool {
+        self.indexed || self.associative || self.integer || self.readonly || self.exported
+    }
+
+    fn matches(&self, variable: &Variable) -> bool {
+        (!self.indexed || matches!(variable.value, Value::Indexed(_)))
+            && (!self.associative || matches!(variable.value, Value::Associative(_)))
+            && (!self.integer || variable.integer)
+            && (!self.readonly || variable.readonly)
+            && (!self.exported || variable.exported)
+    }
+}
+
+/// Implements `declare`/`typeset [-aAirxp] [name[=value] ...]`. Options
+/// with `+` instead of `-` remove attributes. Without operands it lists the
+/// variables, limited to those with the given attributes.
+pub fn declare(
+    options: &[String],
+    operands: &[Operand],
+    variables: &mut Variables,
+) -> Result<String, String> {
+    let mut print = false;
+    let mut on = Attributes::default();
+    let mut off = Attributes::default();
+    for option in options {
+        let (attributes, flags) = match option.strip_prefix('+') {
+            Some(flags) => (&mut off, flags),
+            None => (&mut on, &option[1..]),
+        };
+        for flag in flags.chars() {
+            match flag {
+                'p' => print = true,
+                // There are no local scopes, so all variables are global.
+                'g' => {}
+                _ if attributes.set(flag) => {}
+                _ => {
+                    return Err(format!(
+                        "declare: -{flag}: invalid option\n\
+                         declare: usage: declare [-aAirxp] [name[=value] ...]\n"
+                    ))
+                }
+            }
+        }
+    }
+
+    if operands.is_empty() {
+        return Ok(list(variables, &on, print || on.any()));
+    }
+
+    let mut output = String::new();
+    let mut errors = String::new();
+    for operand in operands {
+        let name = operand.name();
+        if !is_valid_name(name) {
+            errors.push_str(&format!("declare: `{name}': not a valid identifier\n"));
+            continue;
+        }
+
+        if print {
+            match variables.variable(name) {
+                Some(variable) => output.push_str(&describe(name, variable)),
+                None => errors.push_str(&format!("declare: {name}: not found\n")),
+            }
+            continue;
+        }
+
+        if let Err(message) = declare_one(operand, &on, &off, variables) {
+            errors.push_str(&format!("declare: {message}\n"));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(output)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Applies the attributes to one variable and makes its assignment. The
+/// type and integer attributes come first, so that the value is stored
+/// accordingly, and readonly last.
+fn declare_one(
+    operand: &Operand,
+    on: &Attributes,
+    off: &Attributes,
+    variables: &mut Variables,
+) -> Result<(), String> {
+    let name = operand.name();
+    let existing = variables.variable(name);
+    if let Some(variable) = existing.filter(|variable| variable.readonly) {
+        if off.readonly
+            || matches!(operand, Operand::Assignment(_))
+            || on.any() && !on.matches(variable)
+        {
+            return Err(format!("{name}: readonly variable"));
+        }
+    }
+
+    let value = existing.map(|variable| variable.value.clone());
+    let converted = match (value, on.indexed, on.associative) {
+        (Some(Value::Indexed(_)), _, true) => {
+            return Err(format!(
+                "{name}: cannot convert indexed to associative array"
+            ))
+        }
+        (Some(Value::Associative(_)), true, _) => {
+            return Err(format!(
+                "{name}: cannot convert associative to indexed array"
+            ))
+        }
+        (None, true, _) => Some(Value::Indexed(BTreeMap::new())),
+        (Some(Value::Scalar(value)), true, _) => Some(Value::Indexed(match value.is_empty() {
+            true => BTreeMap::new(),
+            false => BTreeMap::from([(0, value)]),
+        })),
+        (None, _, true) => Some(Value::Associative(BTreeMap::new())),
+        (Some(Value::Scalar(value)), _, true) => Some(Value::Associative(match value.is_empty() {
+            true => BTreeMap::new(),
+            false => BTreeMap::from([("0".to_string(), value)]),
+        })),
+        _ => None,
+    };
+
+    let variable = variables.variable_mut(name);
+    if let Some(value) = converted {
+        variable.value = value;
+    }
+    if on.integer {
+        variable.integer = true;
+    }
+    if off.integer {
+        variable.integer = false;
+    }
+
+    if let Operand::Assignment(assignment) = operand {
+        variables.assign(assignment)?;
+    }
+
+    if on.exported || off.exported {
+        variables.set_export(name, on.exported);
+    }
+    if on.readonly {
+        variables.variable_mut(name).readonly = true;
+    }
+
+    Ok(())
+}
+
+/// Lists variables in name order, as `declare` commands with `-p` or as
+/// plain assignments otherwise.
+fn list(variables: &Variables, attributes: &Attributes, as_commands: bool) -> String {
+    let mut names: Vec<&str> = variables.names().collect();
+    names.sort_unstable();
+
+    names
+        .into_iter()
+        .filter_map(|name| Some((name, variables.variable(name)?)))
+        .filter(|(_, variable)| attributes.matches(variable))
+        .map(|(name, variable)| match as_commands {
+            true => describe(name, variable),
+            false => match &variable.value {
+                Value::Scalar(value) => format!("{name}={}\n", quote_if_needed(value)),
+                value => format!("{name}={}\n", format_array(value)),
+            },
+        })
+        .collect()
+}
+
+/// The `declare` command that recreates a variable, as `declare -p` shows.
+fn describe(name: &str, variable: &Variable) -> String {
+    let flags: String = [
+        ('a', matches!(variable.value, Value::Indexed(_))),
+        ('A', matches!(variable.value, Value::Associative(_))),
+        ('i', variable.integer),
+        ('r', variable.readonly),
+        ('x', variable.exported),
+    ]
+    .iter()
+    .filter(|(_, set)| *set)
+    .map(|(flag, _)| *flag)
+    .collect();
+    let flags = match flags.is_empty() {
+        true => "--".to_string(),
+        false => format!("-{flags}"),
+    };
+
+    match &variable.value {
+        Value::Scalar(value) => format!("declare {flags} {name}={}\n", double_quote(value)),
+        value => format!("declare {flags} {name}={}\n", format_array(value)),
+    }
+}
+
+fn format_array(value: &Value) -> String {
+    let elements: Vec<String> = match value {
+        Value::Indexed(elements) => elements
+            .iter()
+            .map(|(index, element)| format!("[{index}]={}", double_quote(element)))
+            .collect(),
+        Value::Associative(elements) => elements
+            .iter()
+            .map(|(key, element)| format!("[{key}]={}", double_quote(element)))
+            .collect(),
+        Value::Scalar(value) => vec![double_quote(value)],
+    };
+
+    format!("({})", elements.join(" "))
+}
+
+fn double_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variables::AssignedValue;
+
+    fn options(options: &[&str]) -> Vec<String> {
+        options.iter().map(|option| option.to_string()).collect()
+    }
+
+    #[test]
+    fn test_declare_integer_and_readonly() {
+        let mut variables = Variables::default();
+        let operands = [Operand::Assignment(Assignment::scalar("n", "2 + 3"))];
+        declare(&options(&["-ir"]), &operands, &mut variables).unwrap();
+
+        assert_eq!(variables.get("n"), Some("5"));
+        let result = variables.assign(&Assignment::scalar("n", "1"));
+        assert_eq!(result, Err("n: readonly variable".to_string()));
+        let result = declare(
+            &options(&["+r"]),
+            &[Operand::Name("n".to_string())],
+            &mut variables,
+        );
+        assert_eq!(result, Err("declare: n: readonly variable\n".to_string()));
+    }
+
+    #[test]
+    fn test_declare_print_arrays() {
+        let mut variables = Variables::default();
+        let mut assignment = Assignment::scalar("m", "");
+        assignment.value = AssignedValue::Compound(vec![
+            (Some("b".to_string()), "two words".to_string()),
+            (Some("a".to_string()), "$1".to_string()),
+        ]);
+        declare(
+            &options(&["-A"]),
+            &[Operand::Assignment(assignment)],
+            &mut variables,
+        )
+        .unwrap();
+        variables
+            .assign(&Assignment::array("l", &["x".to_string()]))
+            .unwrap();
+
+        let operands = [
+            Operand::Name("m".to_string()),
+            Operand::Name("l".to_string()),
+        ];
+        let result = declare(&options(&["-p"]), &operands, &mut variables);
+        let expected = "declare -A m=([a]=\"\\$1\" [b]=\"two words\")\ndeclare -a l=([0]=\"x\")\n";
+        assert_eq!(result, Ok(expected.to_string()));
+    }
+
+    #[test]
+    fn test_declare_refuses_to_convert_arrays() {
+        let mut variables = Variables::default();
+        variables
+            .assign(&Assignment::array("l", &["x".to_string()]))
+            .unwrap();
+
+        let result = declare(
+            &options(&["-A"]),
+            &[Operand::Name("l".to_string())],
+            &mut variables,
+        );
+        assert_eq!(
+            result,
+            Err("declare: l: cannot convert indexed to associative array\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,368 @@
+use crate::shell::Shell;
+use crate::utils::{abbreviate_home, error_message};
+use std::env;
+use std::fs;
+
+/// Makes sure `PWD` names the current directory, as inherited values may
+/// be stale or missing.
+pub fn initialize_pwd(shell: &mut Shell) {
+    let Ok(current) = env::current_dir() else {
+        return;
+    };
+
+    let inherited = shell
+        .variables
+        .get("PWD")
+        .filter(|pwd| pwd.starts_with('/'));
+    let valid = inherited.is_some_and(|pwd| fs::canonicalize(pwd).is_ok_and(|pwd| pwd == current));
+    if !valid {
+        shell
+            .variables
+            .set_exported("PWD", &current.display().to_string());
+    }
+}
+
+/// The current directory as the shell tracks it in `PWD`.
+pub fn current_directory(shell: &Shell) -> String {
+    match shell.variables.get("PWD") {
+        Some(pwd) if !pwd.is_empty() => pwd.to_string(),
+        _ => env::current_dir()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Changes directory, updating `OLDPWD` and `PWD` and running the `chpwd`
+/// hooks. Logically, `..` removes the last component of `PWD` rather than
+/// following symlinks back; physically, `PWD` becomes the resolved path.
+pub fn change_directory(shell: &mut Shell, path: &str, physical: bool) -> Result<(), String> {
+    let old = current_directory(shell);
+    let target = match path.starts_with('/') {
+        true => path.to_string(),
+        false => format!("{old}/{path}"),
+    };
+
+    let new = if physical {
+        env::set_current_dir(path).map_err(|e| format!("{}: {}", path, error_message(&e)))?;
+        env::current_dir()
+            .map(|path| path.display().to_string())
+            .unwrap_or(target)
+    } else {
+        let logical = normalize(&target);
+        env::set_current_dir(&logical)
+            .or_else(|_| env::set_current_dir(path))
+            .map_err(|e| format!("{}: {}", path, error_message(&e)))?;
+        logical
+    };
+
+    shell.variables.set_exported("OLDPWD", &old);
+    shell.variables.set_exported("PWD", &new);
+    shell.run_hook("chpwd", &[]);
+
+    Ok(())
+}
+
+/// Resolves `.` and `..` in an absolute path without looking at the file
+/// system.
+fn normalize(path: &str) -> String {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+
+    format!("/{}", components.join("/"))
+}
+
+/// Looks a relative directory name up in `CDPATH`, returning the directory
+/// found and whether it came from a `CDPATH` entry and so should be printed.
+fn search_cdpath(shell: &Shell, name: &str) -> Option<(String, bool)> {
+    if name.starts_with('/')
+        || name == "."
+        || name == ".."
+        || name.starts_with("./")
+        || name.starts_with("../")
+    {
+        return None;
+    }
+
+    let cdpath = shell.variables.get("CDPATH")?;
+    cdpath.split(':').find_map(|entry| {
+        let candidate = match entry {
+            "" => name.to_string(),
+            _ => format!("{}/{}", entry.trim_end_matches('/'), name),
+        };
+        fs::metadata(&candidate)
+            .is_ok_and(|metadata| metadata.is_dir())
+            .then_some((candidate, !entry.is_empty()))
+    })
+}
+
+/// Implements `cd [-L|-P] [dir]`: no argument goes to `HOME`, `-` goes back
+/// to `OLDPWD` and relative names are looked up in `CDPATH`. Where it went
+/// is printed for `-` and `CDPATH` matches.
+pub fn cd(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let mut physical = false;
+    let mut operands = args.iter();
+    let mut operand = None;
+    for arg in operands.by_ref() {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            "--" => {
+                operand = None;
+                break;
+            }
+            _ => {
+                operand = Some(arg.as_str());
+                break;
+            }
+        }
+    }
+    let operand = operand.or_else(|| operands.next().map(String::as_str));
+
+    let (target, announce) = match operand {
+        None => match shell.variables.get("HOME") {
+            Some(home) if !home.is_empty() => (home.to_string(), false),
+            _ => return Err("cd: HOME not set\n".to_string()),
+        },
+        Some("-") => match shell.variables.get("OLDPWD") {
+            Some(old) if !old.is_empty() => (old.to_string(), true),
+            _ => return Err("cd: OLDPWD not set\n".to_string()),
+        },
+        Some(path) => search_cdpath(shell, path).unwrap_or((path.to_string(), false)),
+    };
+
+    change_directory(shell, &target, physical).map_err(|message| format!("cd: {message}\n"))?;
+
+    Ok(match announce {
+        true => format!("{}\n", current_directory(shell)),
+        false => String::new(),
+    })
+}
+
+/// Implements `pwd [-L|-P]`. The logical path is `PWD`, as long as it
+/// still names the current directory.
+pub fn pwd(args: &[String], shell: &Shell) -> Result<String, String> {
+    let mut physical = false;
+    for arg in args {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            _ => return Err(format!("pwd: {arg}: invalid option\n")),
+        }
+    }
+
+    let current = env::current_dir().map_err(|e| format!("pwd: {}\n", error_message(&e)))?;
+    let logical = current_directory(shell);
+    let logical_is_current = fs::canonicalize(&logical).is_ok_and(|path| path == current);
+
+    Ok(match physical || !logical_is_current {
+        true => format!("{}\n", current.display()),
+        false => format!("{logical}\n"),
+    })
+}
+
+/// The whole stack as `dirs` shows it: the current directory first, then
+/// the saved ones.
+fn full_stack(shell: &Shell) -> Vec<String> {
+    std::iter::once(current_directory(shell))
+        .chain(shell.directory_stack.iter().cloned())
+        .collect()
+}
+
+/// Resolves `+N` (counting from the top) or `-N` (from the bottom) to an
+/// index into a stack of `length` entries.
+fn stack_index(arg: &str, length: usize) -> Option<usize> {
+    let number = arg.get(1..)?.parse::<usize>().ok()?;
+    match arg.chars().next()? {
+        '+' if number < length => Some(number),
+        '-' if number < length => Some(length - 1 - number),
+        _ => None,
+    }
+}
+
+/// Implements `dirs [-clpv] [+N|-N]`.
+pub fn dirs(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let (mut long, mut per_line, mut numbered) = (false, false, false);
+    let mut selected = None;
+    for arg in args {
+        match arg.as_str() {
+            "-c" => {
+                shell.directory_stack.clear();
+                return Ok(String::new());
+            }
+            "-l" => long = true,
+            "-p" => per_line = true,
+            "-v" => numbered = true,
+            _ if arg.starts_with(['+', '-']) => {
+                let length = shell.directory_stack.len() + 1;
+                let index = stack_index(arg, length)
+                    .ok_or_else(|| format!("dirs: {arg}: directory stack index out of range\n"))?;
+                selected = Some(index);
+            }
+            _ => return Err(format!("dirs: {arg}: invalid argument\n")),
+        }
+    }
+
+    let home = shell.variables.get("HOME");
+    let stack: Vec<String> = full_stack(shell)
+        .iter()
+        .map(|directory| match long {
+            true => directory.clone(),
+            false => abbreviate_home(directory, home),
+        })
+        .collect();
+
+    if let Some(index) = selected {
+        return Ok(format!("{}\n", stack[index]));
+    }
+    Ok(format_stack(&stack, per_line, numbered))
+}
+
+fn format_stack(stack: &[String], per_line: bool, numbered: bool) -> String {
+    if numbered {
+        stack
+            .iter()
+            .enumerate()
+            .map(|(index, directory)| format!("{index:>2}  {directory}\n"))
+            .collect()
+    } else if per_line {
+        stack
+            .iter()
+            .map(|directory| format!("{directory}\n"))
+            .collect()
+    } else {
+        format!("{}\n", stack.join(" "))
+    }
+}
+
+/// Implements `pushd [-n] [dir | +N | -N]`. With no argument the top two
+/// entries swap; `+N` and `-N` rotate the stack so that entry is on top.
+/// `-n` adds a directory below the top without changing to it.
+pub fn pushd(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let no_change = args.iter().any(|arg| arg == "-n");
+    let operand = args.iter().find(|arg| *arg != "-n");
+
+    let mut stack = full_stack(shell);
+    match operand {
+        None => {
+            if stack.len() < 2 {
+                return Err("pushd: no other directory\n".to_string());
+            }
+            stack.swap(0, 1);
+        }
+        Some(arg) if arg.len() > 1 && arg.starts_with(['+', '-']) => {
+            let index = stack_index(arg, stack.len())
+                .ok_or_else(|| format!("pushd: {arg}: directory stack index out of range\n"))?;
+            stack.rotate_left(index);
+        }
+        Some(directory) if no_change => {
+            shell.directory_stack.insert(0, directory.clone());
+            return dirs(&[], shell);
+        }
+        Some(directory) => {
+            change_directory(shell, directory, false)
+                .map_err(|message| format!("pushd: {message}\n"))?;
+            shell.directory_stack.insert(0, stack.remove(0));
+            return dirs(&[], shell);
+        }
+    }
+
+    change_directory(shell, &stack[0], false).map_err(|message| format!("pushd: {message}\n"))?;
+    shell.directory_stack = stack.split_off(1);
+    dirs(&[], shell)
+}
+
+/// Implements `popd [-n] [+N | -N]`, dropping the top entry and changing
+/// to the next, or dropping the given entry.
+pub fn popd(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let no_change = args.iter().any(|arg| arg == "-n");
+    let operand = args.iter().find(|arg| *arg != "-n");
+
+    if shell.directory_stack.is_empty() {
+        return Err("popd: directory stack empty\n".to_string());
+    }
+
+    let length = shell.directory_stack.len() + 1;
+    let index = match operand {
+        None => 0,
+        Some(arg) => stack_index(arg, length)
+            .ok_or_else(|| format!("popd: {arg}: directory stack index out of range\n"))?,
+    };
+
+    if index > 0 {
+        shell.directory_stack.remove(index - 1);
+    } else if no_change {
+        shell.directory_stack.remove(0);
+    } else {
+        let next = shell.directory_stack[0].clone();
+        change_directory(shell, &next, false).map_err(|message| format!("popd: {message}\n"))?;
+        shell.directory_stack.remove(0);
+    }
+
+    dirs(&[], shell)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_index_counts_from_either_end() {
+        assert_eq!(stack_index("+0", 3), Some(0));
+        assert_eq!(stack_index("+2", 3), Some(2));
+        assert_eq!(stack_index("-0", 3), Some(2));
+        assert_eq!(stack_index("-2", 3), Some(0));
+        assert_eq!(stack_index("+3", 3), None);
+        assert_eq!(stack_index("+x", 3), None);
+    }
+
+    #[test]
+    fn test_normalize_resolves_dot_dot_logically() {
+        assert_eq!(normalize("/home/me/link/../src/./app"), "/home/me/src/app");
+        assert_eq!(normalize("/../.."), "/");
+        assert_eq!(normalize("/usr//bin/"), "/usr/bin");
+    }
+
+    #[test]
+    fn test_search_cdpath_skips_explicit_relative_names() {
+        let mut shell = Shell::new();
+        shell.variables = crate::variables::Variables::default();
+        shell.variables.set("CDPATH", ":/");
+
+        assert_eq!(
+            search_cdpath(&shell, "tmp"),
+            Some(("/tmp".to_string(), true))
+        );
+        assert_eq!(search_cdpath(&shell, "./tmp"), None);
+        assert_eq!(search_cdpath(&shell, "no-such-dir"), None);
+    }
+
+    #[test]
+    fn test_format_stack_numbered() {
+        let input = vec!["~/src".to_string(), "/tmp".to_string()];
+        let expected = " 0  ~/src\n 1  /tmp\n";
+
+        let result = format_stack(&input, false, true);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_popd_of_entry_leaves_the_current_directory() {
+        let mut shell = Shell::new();
+        shell.directory_stack = vec!["/a".to_string(), "/b".to_string(), "/c".to_string()];
+
+        popd(&["-1".to_string()], &mut shell).unwrap();
+
+        assert_eq!(
+            shell.directory_stack,
+            vec!["/a".to_string(), "/c".to_string()]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use crate::printf::expand_escapes;
+
+/// Implements `echo [-neE] [args]`. Leading words made only of `n`, `e`
+/// and `E` flags are options: `-n` drops the newline, `-e` decodes
+/// backslash escapes and `-E` doesn't. With `xpg_echo` escapes are decoded
+/// by default, as XSI requires. `\c` ends the output, newline included.
+pub fn echo(args: &[String], xpg_echo: bool) -> String {
+    let mut newline = true;
+    let mut escapes = xpg_echo;
+    let mut words = args;
+    while let Some((first, rest)) = words.split_first() {
+        let Some(flags) = first.strip_prefix('-') else {
+            break;
+        };
+        if flags.is_empty() || !flags.chars().all(|c| matches!(c, 'n' | 'e' | 'E')) {
+            break;
+        }
+
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = rest;
+    }
+
+    let text = words.join(" ");
+    if !escapes {
+        return if newline { format!("{text}\n") } else { text };
+    }
+
+    match expand_escapes(&text) {
+        (expanded, true) => expanded,
+        (expanded, false) if newline => format!("{expanded}\n"),
+        (expanded, false) => expanded,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_echo_combined_flags() {
+        let input = args(&["-ne", "a\\tb", "-n"]);
+        let expected = "a\tb -n";
+
+        let result = echo(&input, false);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_echo_stops_at_first_non_option() {
+        let input = args(&["-x", "-n", "a\\nb"]);
+        let expected = "-x -n a\\nb\n";
+
+        let result = echo(&input, false);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_echo_escapes() {
+        let input = args(&["-e", "\\0101\\x42\\u00e9\\\\", "\\cgone"]);
+        let expected = "AB\u{e9}\\ ";
+
+        let result = echo(&input, false);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_echo_xpg_decodes_by_default() {
+        assert_eq!(echo(&args(&["a\\nb"]), true), "a\nb\n");
+        assert_eq!(echo(&args(&["-E", "a\\nb"]), true), "a\\nb\n");
+    }
+}
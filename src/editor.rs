@@ -0,0 +1,640 @@
+use crate::completion;
+use crate::input;
+use crate::input::InputLine;
+use crate::prompt;
+use crate::prompt::{INVISIBLE_END, INVISIBLE_START};
+use crate::shell::Shell;
+use crate::signals;
+use crate::terminal;
+use crate::terminal::{char_width, Input, Key, RawMode};
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+/// An Emacs-style line editor. The terminal is only in raw mode while a
+/// line is being read, and not while completion functions run, so children
+/// always start with it restored.
+#[derive(Debug, Default)]
+pub struct Editor {
+    buffer: Vec<char>,
+    cursor: usize,
+    /// The text most recently killed, for Ctrl-Y.
+    kill_buffer: Vec<char>,
+    prompt: String,
+    /// The row, counted from the prompt's first row, the terminal cursor is on.
+    cursor_row: usize,
+    /// The history entry being shown, or `None` for the line being typed.
+    history_index: Option<usize>,
+    /// The line being typed, kept aside while browsing history.
+    typed_line: Vec<char>,
+    /// The incremental history search in progress, if any.
+    search: Option<Search>,
+    /// The query of the last search, which Ctrl-R on an empty query repeats.
+    last_query: String,
+}
+
+/// An incremental history search, started with Ctrl-R or Ctrl-S.
+#[derive(Debug)]
+struct Search {
+    query: String,
+    reverse: bool,
+    /// The history entry that currently matches.
+    index: Option<usize>,
+    failed: bool,
+    /// The line as it was before the search, which Ctrl-G brings back.
+    original: Vec<char>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor::default()
+    }
+
+    pub fn read_line(&mut self, prompt: &str, shell: &mut Shell) -> InputLine {
+        self.buffer.clear();
+        self.cursor = 0;
+        self.cursor_row = 0;
+        self.prompt = prompt.to_string();
+        self.history_index = None;
+        self.search = None;
+
+        let Ok(raw_mode) = RawMode::enable() else {
+            print!("{prompt}");
+            io::stdout().flush().ok();
+            return input::read_line();
+        };
+        signals::take_interrupt();
+        signals::take_resize();
+        self.refresh();
+
+        loop {
+            let key = match terminal::read_key() {
+                Input::Key(key) => key,
+                Input::Eof if self.buffer.is_empty() => return InputLine::Eof,
+                Input::Eof => return self.accept(),
+                Input::Interrupted => {
+                    if signals::take_interrupt() {
+                        self.finish("^C");
+                        return InputLine::Interrupted;
+                    }
+                    if signals::take_resize() {
+                        self.refresh();
+                    }
+                    continue;
+                }
+            };
+
+            if key == Key::Tab {
+                self.end_search();
+                self.complete(shell, &raw_mode);
+                self.refresh();
+                continue;
+            }
+            if let Some(line) = self.handle_key(key, shell.history.entries()) {
+                return line;
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, history: &[String]) -> Option<InputLine> {
+        if self.search.is_some() && !self.handle_search_key(&key, history) {
+            self.refresh();
+            return None;
+        }
+
+        match key {
+            Key::Enter => return Some(self.accept()),
+            Key::Ctrl('c') => {
+                self.finish("^C");
+                return Some(InputLine::Interrupted);
+            }
+            Key::Ctrl('d') if self.buffer.is_empty() => return Some(InputLine::Eof),
+            Key::Ctrl('d') | Key::Delete => {
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Up | Key::Ctrl('p') => self.show_history(history, true),
+            Key::Down | Key::Ctrl('n') => self.show_history(history, false),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buffer.len(),
+            Key::WordLeft => self.cursor = self.previous_word_start(),
+            Key::WordRight => self.cursor = self.next_word_end(),
+            Key::Ctrl('k') => self.kill(self.cursor..self.buffer.len()),
+            Key::Ctrl('u') => self.kill(0..self.cursor),
+            Key::Ctrl('w') => self.kill(self.previous_blank_delimited_start()..self.cursor),
+            Key::AltBackspace => self.kill(self.previous_word_start()..self.cursor),
+            Key::Alt('d') => self.kill(self.cursor..self.next_word_end()),
+            Key::Ctrl('y') => {
+                let yanked = self.kill_buffer.clone();
+                self.insert(&yanked);
+            }
+            Key::Ctrl('t') => self.transpose(),
+            Key::Ctrl('r') => self.start_search(true),
+            Key::Ctrl('s') => self.start_search(false),
+            Key::Ctrl('l') => {
+                print!("\x1b[H\x1b[2J");
+                self.cursor_row = 0;
+            }
+            Key::Char(c) => self.insert(&[c]),
+            _ => return None,
+        }
+
+        self.refresh();
+        None
+    }
+
+    /// Steps to the previous (older) or next history entry, coming back to
+    /// the line being typed after the newest one.
+    fn show_history(&mut self, history: &[String], older: bool) {
+        let index = match (self.history_index, older) {
+            (None, true) if !history.is_empty() => {
+                self.typed_line = self.buffer.clone();
+                Some(history.len() - 1)
+            }
+            (Some(index), true) if index > 0 => Some(index - 1),
+            (Some(index), false) if index + 1 < history.len() => Some(index + 1),
+            (Some(_), false) => None,
+            _ => return,
+        };
+
+        self.buffer = match index {
+            Some(index) => history[index].chars().collect(),
+            None => std::mem::take(&mut self.typed_line),
+        };
+        self.cursor = self.buffer.len();
+        self.history_index = index;
+    }
+
+    fn start_search(&mut self, reverse: bool) {
+        self.search = Some(Search {
+            query: String::new(),
+            reverse,
+            index: self.history_index,
+            failed: false,
+            original: self.buffer.clone(),
+        });
+    }
+
+    /// Handles a key while searching. Returns whether the key should then be
+    /// handled as usual, which is how Enter and editing keys end the search.
+    fn handle_search_key(&mut self, key: &Key, history: &[String]) -> bool {
+        let Some(search) = self.search.as_mut() else {
+            return true;
+        };
+
+        match key {
+            Key::Char(c) => {
+                search.query.push(*c);
+                self.search_history(history, false);
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                search.query.pop();
+                search.index = None;
+                self.search_history(history, false);
+            }
+            Key::Ctrl('r') | Key::Ctrl('s') => {
+                search.reverse = *key == Key::Ctrl('r');
+                if search.query.is_empty() {
+                    search.query = self.last_query.clone();
+                }
+                self.search_history(history, true);
+            }
+            Key::Ctrl('g') => {
+                self.buffer = std::mem::take(&mut search.original);
+                self.cursor = self.buffer.len();
+                self.search = None;
+            }
+            Key::Escape => self.end_search(),
+            Key::Ctrl('c') => {
+                self.search = None;
+                return true;
+            }
+            _ => {
+                self.end_search();
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Finds the nearest entry containing the query, starting at the current
+    /// match or, when `step` is set, just past it.
+    fn search_history(&mut self, history: &[String], step: bool) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        if search.query.is_empty() {
+            search.failed = false;
+            return;
+        }
+
+        let query = search.query.as_str();
+        let found = if search.reverse {
+            let end = match search.index {
+                Some(index) if step => index,
+                Some(index) => index + 1,
+                None => history.len(),
+            };
+            (0..end).rev().find(|&index| history[index].contains(query))
+        } else {
+            let start = match search.index {
+                Some(index) if step => index + 1,
+                Some(index) => index,
+                None => history.len(),
+            };
+            (start..history.len()).find(|&index| history[index].contains(query))
+        };
+
+        search.failed = found.is_none();
+        let Some(index) = found else {
+            return;
+        };
+        let entry = &history[index];
+        let position = if search.reverse {
+            entry.rfind(query)
+        } else {
+            entry.find(query)
+        };
+
+        search.index = Some(index);
+        self.buffer = entry.chars().collect();
+        self.cursor = position.map_or(0, |position| entry[..position].chars().count());
+    }
+
+    /// Leaves search mode with the matched line ready to edit.
+    fn end_search(&mut self) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+
+        if search.index.is_some() {
+            if self.history_index.is_none() {
+                self.typed_line = search.original;
+            }
+            self.history_index = search.index;
+        }
+        self.last_query = search.query;
+    }
+
+    /// The characters of the buffer the search matched, for highlighting.
+    fn search_match(&self) -> Option<Range<usize>> {
+        let search = self.search.as_ref().filter(|search| !search.failed)?;
+        let length = search.query.chars().count();
+        (length > 0 && search.index.is_some()).then(|| self.cursor..self.cursor + length)
+    }
+
+    /// The prompt, or what bash shows in its place while searching.
+    fn display_prompt(&self) -> String {
+        match &self.search {
+            Some(search) => format!(
+                "({}{}-i-search)`{}': ",
+                if search.failed { "failed " } else { "" },
+                if search.reverse { "reverse" } else { "forward" },
+                search.query
+            ),
+            None => self.prompt.clone(),
+        }
+    }
+
+    /// Completes the word before the cursor as far as the candidates
+    /// agree, listing them when that doesn't get any further.
+    fn complete(&mut self, shell: &mut Shell, raw_mode: &RawMode) {
+        let line: String = self.buffer[..self.cursor].iter().collect();
+        let completion = raw_mode.suspended(|| completion::complete(shell, &line));
+
+        let replacement = match completion.candidates.as_slice() {
+            [] => {
+                print!("\x07");
+                return;
+            }
+            [only] if only.ends_with('/') => completion::escape(only),
+            [only] => format!("{} ", completion::escape(only)),
+            candidates => {
+                let prefix = completion::common_prefix(candidates);
+                if prefix.chars().count() <= completion.word.chars().count() {
+                    self.show_candidates(candidates);
+                    return;
+                }
+                completion::escape(&prefix)
+            }
+        };
+
+        let escaped: Vec<char> = replacement.chars().collect();
+        self.buffer
+            .splice(completion.start..self.cursor, escaped.iter().copied());
+        self.cursor = completion.start + escaped.len();
+    }
+
+    /// Prints the candidates in columns below the input, then starts the
+    /// prompt afresh underneath.
+    fn show_candidates(&mut self, candidates: &[String]) {
+        let cursor = self.cursor;
+        self.finish("");
+        self.cursor = cursor;
+
+        let names: Vec<String> = candidates
+            .iter()
+            .map(|candidate| completion::display_name(candidate).to_string())
+            .collect();
+        print!("{}", completion::format_columns(&names, terminal::width()));
+        self.cursor_row = 0;
+    }
+
+    fn insert(&mut self, chars: &[char]) {
+        self.buffer
+            .splice(self.cursor..self.cursor, chars.iter().copied());
+        self.cursor += chars.len();
+    }
+
+    fn kill(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        self.cursor = range.start;
+        self.kill_buffer = self.buffer.drain(range).collect();
+    }
+
+    /// Swaps the characters around the cursor, like Emacs' `transpose-chars`.
+    fn transpose(&mut self) {
+        if self.cursor == 0 || self.buffer.len() < 2 {
+            return;
+        }
+        if self.cursor == self.buffer.len() {
+            self.cursor -= 1;
+        }
+
+        self.buffer.swap(self.cursor - 1, self.cursor);
+        self.cursor += 1;
+    }
+
+    fn previous_word_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && !self.buffer[index - 1].is_alphanumeric() {
+            index -= 1;
+        }
+        while index > 0 && self.buffer[index - 1].is_alphanumeric() {
+            index -= 1;
+        }
+
+        index
+    }
+
+    fn next_word_end(&self) -> usize {
+        let mut index = self.cursor;
+        while index < self.buffer.len() && !self.buffer[index].is_alphanumeric() {
+            index += 1;
+        }
+        while index < self.buffer.len() && self.buffer[index].is_alphanumeric() {
+            index += 1;
+        }
+
+        index
+    }
+
+    /// Ctrl-W treats everything between blanks as one word.
+    fn previous_blank_delimited_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && self.buffer[index - 1].is_whitespace() {
+            index -= 1;
+        }
+        while index > 0 && !self.buffer[index - 1].is_whitespace() {
+            index -= 1;
+        }
+
+        index
+    }
+
+    fn accept(&mut self) -> InputLine {
+        self.finish("");
+        InputLine::Line(self.buffer.iter().collect())
+    }
+
+    /// Leaves the cursor after the input, followed by `marker`, on a fresh line.
+    fn finish(&mut self, marker: &str) {
+        self.cursor = self.buffer.len();
+        self.refresh();
+        println!("{marker}");
+        io::stdout().flush().ok();
+    }
+
+    /// Redraws the prompt and the input, which may wrap over several rows,
+    /// and puts the terminal cursor back where the edit cursor is.
+    fn refresh(&mut self) {
+        let columns = terminal::width();
+        let mut output = String::new();
+
+        if self.cursor_row > 0 {
+            output.push_str(&format!("\x1b[{}A", self.cursor_row));
+        }
+        output.push_str("\r\x1b[J");
+        let prompt = self.display_prompt();
+        output.push_str(&prompt::strip_markers(&prompt));
+        match self.search_match() {
+            Some(matched) => {
+                output.extend(self.buffer[..matched.start].iter());
+                output.push_str("\x1b[7m");
+                output.extend(self.buffer[matched.clone()].iter());
+                output.push_str("\x1b[0m");
+                output.extend(self.buffer[matched.end..].iter());
+            }
+            None => output.extend(self.buffer.iter()),
+        }
+
+        let prompt = prompt.chars();
+        let (mut end_row, end_column) =
+            layout(prompt.clone().chain(self.buffer.iter().copied()), columns);
+        let at_end = self.cursor == self.buffer.len();
+        if at_end && end_column == columns {
+            output.push('\n');
+            end_row += 1;
+        }
+
+        let (mut row, mut column) = layout(
+            prompt.chain(self.buffer[..self.cursor].iter().copied()),
+            columns,
+        );
+        let next_width = self.buffer.get(self.cursor).map_or(0, |&c| char_width(c));
+        if column + next_width > columns || (at_end && column == columns) {
+            row += 1;
+            column = 0;
+        }
+
+        if end_row > row {
+            output.push_str(&format!("\x1b[{}A", end_row - row));
+        }
+        output.push('\r');
+        if column > 0 {
+            output.push_str(&format!("\x1b[{column}C"));
+        }
+
+        print!("{output}");
+        io::stdout().flush().ok();
+        self.cursor_row = row;
+    }
+}
+
+/// The row and column the terminal cursor reaches after printing `chars`
+/// from the start of a row `columns` wide. A column equal to `columns`
+/// means the terminal is waiting to wrap. Text between the invisible
+/// markers and ANSI escape sequences take up no room.
+fn layout(chars: impl Iterator<Item = char>, columns: usize) -> (usize, usize) {
+    let (mut row, mut column) = (0, 0);
+    let mut invisible = false;
+    let mut chars = chars.peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            INVISIBLE_START => invisible = true,
+            INVISIBLE_END => invisible = false,
+            '\x1b' if chars.peek() == Some(&'[') => {
+                chars.next();
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if invisible || c == INVISIBLE_END || c == '\x1b' {
+            continue;
+        }
+
+        if c == '\n' {
+            row += 1;
+            column = 0;
+            continue;
+        }
+
+        let width = char_width(c);
+        if column + width > columns {
+            row += 1;
+            column = 0;
+        }
+        column += width;
+    }
+
+    (row, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor_with(text: &str, cursor: usize) -> Editor {
+        Editor {
+            buffer: text.chars().collect(),
+            cursor,
+            ..Editor::default()
+        }
+    }
+
+    #[test]
+    fn test_kill_previous_word_and_yank() {
+        let mut editor = editor_with("git commit --amend", 10);
+
+        editor.kill(editor.previous_word_start()..editor.cursor);
+        editor.cursor = editor.buffer.len();
+        let yanked = editor.kill_buffer.clone();
+        editor.insert(&yanked);
+
+        let result: String = editor.buffer.iter().collect();
+        assert_eq!(result, "git  --amendcommit");
+    }
+
+    #[test]
+    fn test_history_navigation_restores_typed_line() {
+        let history = vec!["ls".to_string(), "pwd".to_string()];
+        let mut editor = editor_with("ech", 3);
+
+        editor.show_history(&history, true);
+        editor.show_history(&history, true);
+        editor.show_history(&history, true);
+        let oldest: String = editor.buffer.iter().collect();
+        editor.show_history(&history, false);
+        editor.show_history(&history, false);
+
+        let result: String = editor.buffer.iter().collect();
+        assert_eq!(oldest, "ls");
+        assert_eq!(result, "ech");
+    }
+
+    #[test]
+    fn test_reverse_search_steps_to_older_matches() {
+        let history = vec![
+            "git status".to_string(),
+            "ls".to_string(),
+            "git commit".to_string(),
+        ];
+        let mut editor = editor_with("", 0);
+
+        editor.handle_key(Key::Ctrl('r'), &history);
+        for c in "git".chars() {
+            editor.handle_key(Key::Char(c), &history);
+        }
+        let newest: String = editor.buffer.iter().collect();
+        editor.handle_key(Key::Ctrl('r'), &history);
+        editor.handle_key(Key::Ctrl('r'), &history);
+
+        let result: String = editor.buffer.iter().collect();
+        assert_eq!(newest, "git commit");
+        assert_eq!(result, "git status");
+        assert!(editor.search.as_ref().unwrap().failed);
+    }
+
+    #[test]
+    fn test_escape_ends_search_on_the_match() {
+        let history = vec!["echo hello".to_string(), "pwd".to_string()];
+        let mut editor = editor_with("", 0);
+
+        editor.handle_key(Key::Ctrl('r'), &history);
+        editor.handle_key(Key::Char('l'), &history);
+        editor.handle_key(Key::Char('l'), &history);
+        let matched = editor.search_match();
+        editor.handle_key(Key::Escape, &history);
+
+        assert_eq!(matched, Some(7..9));
+        assert!(editor.search.is_none());
+        assert_eq!(editor.cursor, 7);
+        assert_eq!(editor.history_index, Some(0));
+    }
+
+    #[test]
+    fn test_transpose_at_end_of_line() {
+        let mut editor = editor_with("sl", 2);
+
+        editor.transpose();
+
+        let result: String = editor.buffer.iter().collect();
+        assert_eq!(result, "ls");
+        assert_eq!(editor.cursor, 2);
+    }
+
+    #[test]
+    fn test_layout_skips_invisible_text_and_colours() {
+        let input = "\x01\x1b]0;title\x07\x02\x1b[1;32m$\x1b[0m abc";
+        let expected = (0, 5);
+
+        let result = layout(input.chars(), 80);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_layout_wraps_wide_characters_early() {
+        let input = "$ abc日本";
+        let expected = (1, 4);
+
+        let result = layout(input.chars(), 6);
+        assert_eq!(result, expected);
+    }
+}
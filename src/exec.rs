@@ -0,0 +1,293 @@
+use crate::parser::Token;
+use crate::shell::Shell;
+use crate::signals;
+use crate::utils::{error_message, open_without_clobbering};
+use std::ffi::CString;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::os::fd::IntoRawFd;
+
+/// A redirection `exec` makes to the shell's own file descriptors.
+#[derive(Debug, PartialEq, Eq)]
+enum FdRedirection {
+    /// `n>file`, `n>|file`, `n>>file`, `n<file` and `n<>file`.
+    Open { fd: i32, file: String, mode: Mode },
+    /// `n>&m` and `n<&m`.
+    Duplicate { fd: i32, source: i32 },
+    /// `n>&-` and `n<&-`.
+    Close { fd: i32 },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mode {
+    /// `>|`, and `>` without `noclobber`.
+    Truncate,
+    /// `>` under `set -o noclobber`.
+    NoClobber,
+    Append,
+    Read,
+    /// `<>`, which opens the file for both without truncating it.
+    ReadWrite,
+}
+
+/// Operators in the order they must be tried, longest first.
+const OPERATORS: &[&str] = &[">>", ">|", ">&", "<&", "<>", ">", "<"];
+
+/// Implements `exec [command [args...]]` with redirections. The
+/// redirections change the shell's own descriptors, so without a command
+/// they last for all later commands. With one, the shell is replaced by
+/// it; if that fails, a non-interactive shell exits.
+pub fn exec(args: &[Token], shell: &mut Shell) -> Result<i32, String> {
+    let (redirections, command) = parse(args, shell.options.noclobber)?;
+
+    io::stdout().flush().ok();
+    for redirection in &redirections {
+        apply(redirection)?;
+    }
+
+    let Some(name) = command.first() else {
+        return Ok(0);
+    };
+    let c_args = command
+        .iter()
+        .map(|arg| CString::new(arg.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("exec: {name}: invalid argument"))?;
+    let mut argv: Vec<*const libc::c_char> = c_args.iter().map(|arg| arg.as_ptr()).collect();
+    argv.push(std::ptr::null());
+
+    let dispositions = signals::Dispositions::save();
+    signals::restore_default_signals().map_err(|e| format!("exec: {}", error_message(&e)))?;
+    unsafe { libc::execvp(argv[0], argv.as_ptr()) };
+
+    let error = io::Error::last_os_error();
+    dispositions.restore();
+    let status = match error.kind() {
+        io::ErrorKind::NotFound => {
+            eprintln!("exec: {name}: not found");
+            127
+        }
+        _ => {
+            eprintln!("exec: {name}: {}", error_message(&error));
+            126
+        }
+    };
+    if !shell.interactive {
+        shell.exit(status);
+    }
+
+    Ok(status)
+}
+
+/// Splits the arguments into redirections and the command. Only operators
+/// written out unquoted count, so `exec echo '>' x` prints `> x`.
+fn parse(args: &[Token], noclobber: bool) -> Result<(Vec<FdRedirection>, Vec<String>), String> {
+    let mut redirections = Vec::new();
+    let mut command = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(Token { text: arg, unquoted }) = args.next() {
+        let digits = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+        let rest = &arg[digits..];
+        let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(**operator) && digits + operator.len() <= *unquoted)
+        else {
+            command.push(arg.clone());
+            continue;
+        };
+
+        let target = match &rest[operator.len()..] {
+            "" => args
+                .next()
+                .ok_or("syntax error near unexpected token `newline'")?
+                .text
+                .clone(),
+            target => target.to_string(),
+        };
+        let fd = match &arg[..digits] {
+            "" if operator.starts_with('<') => 0,
+            "" => 1,
+            fd => fd
+                .parse()
+                .map_err(|_| format!("{fd}: bad file descriptor"))?,
+        };
+
+        let mode = match *operator {
+            ">&" | "<&" if target == "-" => {
+                redirections.push(FdRedirection::Close { fd });
+                continue;
+            }
+            ">&" | "<&" => {
+                let source = target
+                    .parse()
+                    .map_err(|_| format!("{target}: ambiguous redirect"))?;
+                redirections.push(FdRedirection::Duplicate { fd, source });
+                continue;
+            }
+            ">" if noclobber => Mode::NoClobber,
+            ">" | ">|" => Mode::Truncate,
+            ">>" => Mode::Append,
+            "<>" => Mode::ReadWrite,
+            _ => Mode::Read,
+        };
+        redirections.push(FdRedirection::Open {
+            fd,
+            file: target,
+            mode,
+        });
+    }
+
+    Ok((redirections, command))
+}
+
+fn apply(redirection: &FdRedirection) -> Result<(), String> {
+    match redirection {
+        FdRedirection::Open { fd, file, mode } => {
+            let error = |e: io::Error| format!("{file}: {}", error_message(&e));
+            let mut options = OpenOptions::new();
+            let opened = match mode {
+                Mode::Truncate => options.write(true).create(true).truncate(true).open(file),
+                Mode::NoClobber => open_without_clobbering(file),
+                Mode::Append => options.append(true).create(true).open(file),
+                Mode::Read => options.read(true).open(file),
+                Mode::ReadWrite => options.read(true).write(true).create(true).open(file),
+            };
+            let opened = opened.map_err(error)?.into_raw_fd();
+
+            // Rust opens files close-on-exec, which `dup2` clears on the copy.
+            let result = match opened == *fd {
+                true => unsafe { libc::fcntl(opened, libc::F_SETFD, 0) },
+                false => {
+                    let result = unsafe { libc::dup2(opened, *fd) };
+                    unsafe { libc::close(opened) };
+                    result
+                }
+            };
+            if result < 0 {
+                return Err(error(io::Error::last_os_error()));
+            }
+        }
+        FdRedirection::Duplicate { fd, source } => {
+            if unsafe { libc::dup2(*source, *fd) } < 0 {
+                let e = io::Error::last_os_error();
+                return Err(format!("{source}: {}", error_message(&e)));
+            }
+        }
+        // As in other shells, closing a descriptor that isn't open is fine.
+        FdRedirection::Close { fd } => {
+            unsafe { libc::close(*fd) };
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    fn args(text: &str) -> Vec<Token> {
+        let token = |word: &str| Token {
+            text: word.to_string(),
+            unquoted: word.len(),
+        };
+        text.split_whitespace().map(token).collect()
+    }
+
+    #[test]
+    fn test_parse_redirections_and_command() {
+        let input = args("3> out 2>>log <in 4<&- 5>&1 cat -n");
+        let expected = (
+            vec![
+                FdRedirection::Open {
+                    fd: 3,
+                    file: "out".to_string(),
+                    mode: Mode::NoClobber,
+                },
+                FdRedirection::Open {
+                    fd: 2,
+                    file: "log".to_string(),
+                    mode: Mode::Append,
+                },
+                FdRedirection::Open {
+                    fd: 0,
+                    file: "in".to_string(),
+                    mode: Mode::Read,
+                },
+                FdRedirection::Close { fd: 4 },
+                FdRedirection::Duplicate { fd: 5, source: 1 },
+            ],
+            words("cat -n"),
+        );
+
+        let result = parse(&input, true);
+        assert_eq!(result, Ok(expected));
+        assert!(parse(&args("3>"), false).is_err());
+    }
+
+    #[test]
+    fn test_parse_leaves_quoted_operators_alone() {
+        let quoted = |text: &str, unquoted: usize| Token {
+            text: text.to_string(),
+            unquoted,
+        };
+
+        let input = [quoted("echo", 4), quoted(">", 0), quoted("hello", 5)];
+        assert_eq!(parse(&input, false), Ok((vec![], words("echo > hello"))));
+
+        let input = [quoted("3>out file", 2)];
+        let expected = vec![FdRedirection::Open {
+            fd: 3,
+            file: "out file".to_string(),
+            mode: Mode::Truncate,
+        }];
+        assert_eq!(parse(&input, false), Ok((expected, vec![])));
+    }
+
+    #[test]
+    fn test_failed_exec_keeps_signals_ignored_by_trap() {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let mut shell = Shell::new();
+            shell.interactive = true;
+            signals::ignore_signal(libc::SIGINT);
+            let status = exec(&args("exec_test_missing_command"), &mut shell);
+
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigaction(libc::SIGINT, std::ptr::null(), &mut action) };
+            let ignored = action.sa_sigaction == libc::SIG_IGN;
+            unsafe { libc::_exit(i32::from(status == Ok(127) && ignored)) };
+        }
+
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        assert_eq!(libc::WEXITSTATUS(status), 1);
+    }
+
+    #[test]
+    fn test_apply_opens_and_closes_descriptors() {
+        let path = std::env::temp_dir().join(format!("exec_test_{}", std::process::id()));
+        let file = path.to_str().unwrap().to_string();
+        let fd = 47;
+
+        let redirection = FdRedirection::Open {
+            fd,
+            file: file.clone(),
+            mode: Mode::Truncate,
+        };
+        assert_eq!(apply(&redirection), Ok(()));
+        assert_eq!(unsafe { libc::write(fd, b"hi".as_ptr().cast(), 2) }, 2);
+        assert_eq!(unsafe { libc::fcntl(fd, libc::F_GETFD) }, 0);
+        assert_eq!(apply(&FdRedirection::Close { fd }), Ok(()));
+        assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hi");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,574 @@
+use crate::arithmetic;
+use crate::shell::Shell;
+use crate::variables::{is_valid_name, Assignment};
+use std::iter::Peekable;
+use std::str::Chars;
+
+pub enum Expansion {
+    Text(String),
+    /// `$@`, which stays one word per positional parameter inside quotes.
+    Fields(Vec<String>),
+}
+
+/// Expands the parameter or `$(...)` command substitution that follows a
+/// `$`, consuming it from `chars`. Returns `None` when the `$` doesn't
+/// start an expansion and is literal.
+pub fn expand_dollar(chars: &mut Peekable<Chars>, shell: &mut Shell) -> Option<Expansion> {
+    let name = match chars.peek()? {
+        '(' => {
+            chars.next();
+            let command = read_substitution(chars)?;
+            return Some(Expansion::Text(shell.command_substitution(&command)));
+        }
+        '{' => {
+            chars.next();
+            let inner = read_braced(chars)?;
+            return Some(expand_braced(&inner, shell));
+        }
+        '@' => {
+            chars.next();
+            return Some(Expansion::Fields(shell.positional.clone()));
+        }
+        '?' | '#' | '$' | '*' | '-' | '0'..='9' => chars.next()?.to_string(),
+        c if *c == '_' || c.is_ascii_alphabetic() => {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c != '_' && !c.is_ascii_alphanumeric() {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            name
+        }
+        _ => return None,
+    };
+
+    check_set(&name, is_set(&name, shell), shell);
+    Some(Expansion::Text(parameter_value(&name, shell)))
+}
+
+/// Runs the command of a `` `...` `` substitution, whose opening backquote
+/// has already been consumed. Returns `None` when it isn't closed.
+pub fn expand_backquote(chars: &mut Peekable<Chars>, shell: &mut Shell) -> Option<Expansion> {
+    let command = read_backquoted(chars)?;
+    Some(Expansion::Text(shell.command_substitution(&command)))
+}
+
+/// Reads the command of a `$(...)` substitution, whose `$(` has already
+/// been consumed, up to and including the matching `)`. Quotes and nested
+/// parentheses inside are skipped over. Returns `None` if the input ends
+/// first.
+pub fn read_substitution(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut command = String::new();
+    let mut depth = 0;
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut to_escape = false;
+    for c in chars.by_ref() {
+        if to_escape {
+            to_escape = false;
+        } else {
+            match c {
+                '\\' if !in_single_quote => to_escape = true,
+                '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+                '"' if !in_single_quote => in_double_quote = !in_double_quote,
+                _ if in_single_quote || in_double_quote => {}
+                '(' => depth += 1,
+                ')' if depth == 0 => return Some(command),
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+        command.push(c);
+    }
+
+    None
+}
+
+/// Reads the inside of `${...}`, whose `${` has already been consumed, up
+/// to the matching `}`. Braces of nested expansions such as the default in
+/// `${a:-${b}}` are skipped over, as are quoted ones. Returns `None` if the
+/// input ends first.
+pub fn read_braced(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut inner = String::new();
+    let mut depth = 0;
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut to_escape = false;
+    for c in chars.by_ref() {
+        if to_escape {
+            to_escape = false;
+        } else {
+            match c {
+                '\\' if !in_single_quote => to_escape = true,
+                '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+                '"' if !in_single_quote => in_double_quote = !in_double_quote,
+                _ if in_single_quote || in_double_quote => {}
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(inner),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        inner.push(c);
+    }
+
+    None
+}
+
+/// Reads a backquoted command up to the closing backquote, undoing the
+/// backslash escapes that are special inside backquotes.
+pub fn read_backquoted(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut command = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Some(command),
+            '\\' => match chars.next()? {
+                c @ ('`' | '\\' | '$') => command.push(c),
+                c => {
+                    command.push('\\');
+                    command.push(c);
+                }
+            },
+            _ => command.push(c),
+        }
+    }
+
+    None
+}
+
+/// Substitutes parameters and commands in `text` without quote removal or
+/// splitting, for values such as `ENV` and prompts.
+pub fn expand_parameters(text: &str, shell: &mut Shell) -> String {
+    let mut expanded = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '`' {
+            match expand_backquote(&mut chars, shell) {
+                Some(Expansion::Text(value)) => expanded.push_str(&value),
+                _ => expanded.push('`'),
+            }
+            continue;
+        }
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        match expand_dollar(&mut chars, shell) {
+            None => expanded.push('$'),
+            Some(Expansion::Text(value)) => expanded.push_str(&value),
+            Some(Expansion::Fields(fields)) => expanded.push_str(&fields.join(" ")),
+        }
+    }
+
+    expanded
+}
+
+/// Expands a single word without field splitting, as inside `[[ ]]`,
+/// returning its pieces along with whether each was quoted. A leading
+/// unquoted `~` becomes `HOME`.
+pub fn expand_word_segments(word: &str, shell: &mut Shell) -> Vec<(String, bool)> {
+    let mut segments: Vec<(String, bool)> = Vec::new();
+    let mut push = |text: &str, quoted: bool| match segments.last_mut() {
+        Some((last, last_quoted)) if *last_quoted == quoted => last.push_str(text),
+        _ => segments.push((text.to_string(), quoted)),
+    };
+
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut chars = word.chars().peekable();
+    if word == "~" || word.starts_with("~/") {
+        chars.next();
+        let home = shell.variables.get("HOME").unwrap_or_default().to_string();
+        push(&home, false);
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+            '"' if !in_single_quote => in_double_quote = !in_double_quote,
+            _ if in_single_quote => push(&c.to_string(), true),
+            '\\' => match chars.next() {
+                Some(next) if in_double_quote && !matches!(next, '"' | '\\' | '`' | '$') => {
+                    push(&format!("\\{next}"), true);
+                }
+                Some(next) => push(&next.to_string(), true),
+                None => push("\\", in_double_quote),
+            },
+            '$' | '`' => {
+                let expansion = match c {
+                    '$' => expand_dollar(&mut chars, shell),
+                    _ => expand_backquote(&mut chars, shell),
+                };
+                match expansion {
+                    None => push(&c.to_string(), in_double_quote),
+                    Some(Expansion::Text(value)) => push(&value, in_double_quote),
+                    Some(Expansion::Fields(fields)) => push(&fields.join(" "), in_double_quote),
+                }
+            }
+            _ => push(&c.to_string(), in_double_quote),
+        }
+    }
+
+    segments
+}
+
+/// Expands a word without field splitting, as assignment values are.
+pub fn expand_word(word: &str, shell: &mut Shell) -> String {
+    expand_word_segments(word, shell)
+        .into_iter()
+        .map(|(text, _)| text)
+        .collect()
+}
+
+/// Expands the inside of `${...}`: a parameter or `name[subscript]`, the
+/// lengths `#name`, `#name[@]` and `#name[subscript]`, the keys
+/// `!name[@]`, `parameter:offset:length` slices of strings and lists, and
+/// the `-`, `=`, `?` and `+` operators, with or without a colon.
+fn expand_braced(inner: &str, shell: &mut Shell) -> Expansion {
+    if let Some((parameter, operator, word)) = split_operator(inner) {
+        return expand_operator(parameter, operator, word, shell);
+    }
+
+    if let Some(parameter) = inner.strip_prefix('#').filter(|rest| !rest.is_empty()) {
+        let length = match split_subscript(parameter) {
+            Some((name, "@" | "*")) => shell.variables.elements(name).len(),
+            Some((name, subscript)) => {
+                let subscript = expand_parameters(subscript, shell);
+                let element = shell.variables.element(name, &subscript);
+                check_set(&format!("{name}[{subscript}]"), element.is_some(), shell);
+                element.unwrap_or_default().chars().count()
+            }
+            None if parameter == "@" || parameter == "*" => shell.positional.len(),
+            None => {
+                check_set(parameter, is_set(parameter, shell), shell);
+                parameter_value(parameter, shell).chars().count()
+            }
+        };
+        return Expansion::Text(length.to_string());
+    }
+
+    if let Some((name, all @ ("@" | "*"))) = inner.strip_prefix('!').and_then(split_subscript) {
+        let keys = shell
+            .variables
+            .variable(name)
+            .map(|variable| variable.keys())
+            .unwrap_or_default();
+        return match all {
+            "@" => Expansion::Fields(keys),
+            _ => Expansion::Text(keys.join(" ")),
+        };
+    }
+
+    let (parameter, slice) = split_slice(inner);
+    // Lists are kept apart so that slices select elements, not characters.
+    let (list, fields) = match split_subscript(parameter) {
+        Some((name, all @ ("@" | "*"))) => (Some(shell.variables.elements(name)), all == "@"),
+        Some((name, subscript)) => {
+            let subscript = expand_parameters(subscript, shell);
+            let element = shell.variables.element(name, &subscript);
+            check_set(&format!("{name}[{subscript}]"), element.is_some(), shell);
+            return match slice {
+                Some(slice) => Expansion::Text(slice_text(&element.unwrap_or_default(), slice, shell)),
+                None => Expansion::Text(element.unwrap_or_default()),
+            };
+        }
+        None if parameter == "@" || parameter == "*" => {
+            // Slices of the positional parameters count `$0` as the first.
+            let list = match slice {
+                Some(_) => std::iter::once(shell.script_name.clone())
+                    .chain(shell.positional.iter().cloned())
+                    .collect(),
+                None => shell.positional.clone(),
+            };
+            (Some(list), parameter == "@")
+        }
+        None => {
+            check_set(parameter, is_set(parameter, shell), shell);
+            (None, false)
+        }
+    };
+
+    match (list, slice) {
+        (Some(list), slice) => {
+            let list = match slice {
+                Some(slice) => slice_list(list, slice, shell),
+                None => list,
+            };
+            match fields {
+                true => Expansion::Fields(list),
+                false => Expansion::Text(list.join(" ")),
+            }
+        }
+        (None, Some(slice)) => {
+            let value = parameter_value(parameter, shell);
+            Expansion::Text(slice_text(&value, slice, shell))
+        }
+        (None, None) => Expansion::Text(parameter_value(parameter, shell)),
+    }
+}
+
+/// Splits `parameter`, `operator` and `word` apart in `${parameter:-word}`
+/// and the like.
+fn split_operator(inner: &str) -> Option<(&str, &str, &str)> {
+    let length = match inner.chars().next()? {
+        c if c == '_' || c.is_ascii_alphabetic() => {
+            let name = inner
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(inner.len());
+            match inner[name..].starts_with('[') {
+                true => name + inner[name..].find(']')? + 1,
+                false => name,
+            }
+        }
+        '0'..='9' => inner
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(inner.len()),
+        '@' | '*' | '#' | '?' | '-' | '$' | '!' => 1,
+        _ => return None,
+    };
+
+    let (parameter, rest) = inner.split_at(length);
+    let operator_length = match rest.starts_with(':') {
+        true => 2,
+        false => 1,
+    };
+    let operator = rest.get(..operator_length)?;
+    operator
+        .ends_with(['-', '=', '?', '+'])
+        .then(|| (parameter, operator, &rest[operator_length..]))
+}
+
+/// Expands `${parameter-word}` and the rest, which test whether the
+/// parameter is set, or with a colon also whether it's empty. `set -u`
+/// doesn't object to unset parameters here.
+fn expand_operator(parameter: &str, operator: &str, word: &str, shell: &mut Shell) -> Expansion {
+    let value = match split_subscript(parameter) {
+        Some((name, "@" | "*")) => Some(shell.variables.elements(name).join(" "))
+            .filter(|_| shell.variables.variable(name).is_some()),
+        Some((name, subscript)) => {
+            let subscript = expand_parameters(subscript, shell);
+            shell.variables.element(name, &subscript)
+        }
+        None if parameter == "@" || parameter == "*" => {
+            Some(shell.positional.join(" ")).filter(|_| !shell.positional.is_empty())
+        }
+        None => Some(parameter_value(parameter, shell)).filter(|_| is_set(parameter, shell)),
+    };
+    let present = match operator.starts_with(':') {
+        true => value.is_some_and(|value| !value.is_empty()),
+        false => value.is_some(),
+    };
+
+    match operator.trim_start_matches(':') {
+        "+" if present => Expansion::Text(expand_word(word, shell)),
+        "+" => Expansion::Text(String::new()),
+        _ if present => expand_braced(parameter, shell),
+        "-" => Expansion::Text(expand_word(word, shell)),
+        "=" => {
+            let value = expand_word(word, shell);
+            let assignment = match split_subscript(parameter) {
+                Some((name, subscript)) => Some(Assignment {
+                    index: Some(expand_parameters(subscript, shell)),
+                    ..Assignment::scalar(name, &value)
+                }),
+                None if is_valid_name(parameter) => Some(Assignment::scalar(parameter, &value)),
+                None => None,
+            };
+            let result = match assignment {
+                Some(assignment) => shell.variables.assign(&assignment),
+                None => Err(format!("${parameter}: cannot assign in this way")),
+            };
+            if let Err(message) = result {
+                eprintln!("{}: {message}", shell.script_name);
+                shell.expansion_failed = true;
+            }
+            expand_braced(parameter, shell)
+        }
+        _ => {
+            let message = match expand_word(word, shell) {
+                message if !message.is_empty() => message,
+                _ if operator == ":?" => "parameter null or not set".to_string(),
+                _ => "parameter not set".to_string(),
+            };
+            eprintln!("{}: {parameter}: {message}", shell.script_name);
+            shell.expansion_failed = true;
+            Expansion::Text(String::new())
+        }
+    }
+}
+
+/// Splits `name[subscript]` into its parts.
+fn split_subscript(parameter: &str) -> Option<(&str, &str)> {
+    let (name, rest) = parameter.split_once('[')?;
+    let subscript = rest.strip_suffix(']')?;
+    is_valid_name(name).then_some((name, subscript))
+}
+
+/// Splits `parameter:offset[:length]` at the colon. A colon followed by
+/// `-`, `=`, `?` or `+` doesn't start a slice.
+fn split_slice(inner: &str) -> (&str, Option<&str>) {
+    let start = match inner.find('[') {
+        Some(open) => inner[open..].find(']').map_or(inner.len(), |close| open + close),
+        None => 0,
+    };
+    match inner[start..].find(':') {
+        Some(colon) => {
+            let (parameter, slice) = inner.split_at(start + colon);
+            let slice = &slice[1..];
+            match slice.starts_with(['-', '=', '?', '+']) {
+                true => (inner, None),
+                false => (parameter, Some(slice)),
+            }
+        }
+        None => (inner, None),
+    }
+}
+
+/// Resolves `offset[:length]` against something `count` long, returning
+/// where the slice starts and how long it is. Negative offsets count from
+/// the end, and a negative length leaves that many off the end.
+fn slice_bounds(slice: &str, count: usize, shell: &mut Shell) -> (usize, usize) {
+    let mut evaluate = |text: &str| {
+        let text = expand_parameters(text, shell);
+        arithmetic::evaluate(&text, &shell.variables).unwrap_or(0)
+    };
+    let (offset, length) = match slice.split_once(':') {
+        Some((offset, length)) => (evaluate(offset), Some(evaluate(length))),
+        None => (evaluate(slice), None),
+    };
+
+    let count = count as i64;
+    let start = match offset {
+        offset if offset < 0 => (count + offset).max(0),
+        offset => offset.min(count),
+    };
+    let end = match length {
+        None => count,
+        Some(length) if length < 0 => (count + length).max(start),
+        Some(length) => (start + length).min(count),
+    };
+
+    (start as usize, (end - start) as usize)
+}
+
+fn slice_text(value: &str, slice: &str, shell: &mut Shell) -> String {
+    let (start, length) = slice_bounds(slice, value.chars().count(), shell);
+    value.chars().skip(start).take(length).collect()
+}
+
+fn slice_list(list: Vec<String>, slice: &str, shell: &mut Shell) -> Vec<String> {
+    let (start, length) = slice_bounds(slice, list.len(), shell);
+    list.into_iter().skip(start).take(length).collect()
+}
+
+/// Whether a parameter is set: a variable that exists, a positional
+/// parameter within `$#`, or any of the special parameters.
+fn is_set(name: &str, shell: &Shell) -> bool {
+    match name {
+        _ if name.chars().all(|c| c.is_ascii_digit()) => name
+            .parse::<usize>()
+            .is_ok_and(|index| index <= shell.positional.len()),
+        _ if is_valid_name(name) => shell.variables.variable(name).is_some(),
+        _ => true,
+    }
+}
+
+/// Under `set -u`, reports expanding `parameter` if it isn't set and marks
+/// the expansion as failed, so that the command isn't run.
+fn check_set(parameter: &str, set: bool, shell: &mut Shell) {
+    if shell.options.nounset && !set {
+        eprintln!("{}: {parameter}: unbound variable", shell.script_name);
+        shell.expansion_failed = true;
+    }
+}
+
+fn parameter_value(name: &str, shell: &Shell) -> String {
+    match name {
+        "?" => shell.last_status.to_string(),
+        "-" => {
+            let flags = shell.options.short_flags();
+            if shell.interactive {
+                format!("{flags}i")
+            } else {
+                flags
+            }
+        }
+        "#" => shell.positional.len().to_string(),
+        "$" => shell.pid.to_string(),
+        "*" => shell.positional.join(" "),
+        "0" => shell.script_name.clone(),
+        _ if name.chars().all(|c| c.is_ascii_digit()) => name
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| index.checked_sub(1))
+            .and_then(|index| shell.positional.get(index))
+            .cloned()
+            .unwrap_or_default(),
+        _ if is_valid_name(name) => shell.variables.get(name).unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_default_and_alternate_values() {
+        let mut shell = Shell::new();
+        shell.variables.set("EXPANSION_EMPTY", "");
+        shell.variables.set("EXPANSION_SET", "value");
+        let input = "${EXPANSION_UNSET:-d} ${EXPANSION_SET:-d} ${EXPANSION_EMPTY-d}|\
+                     ${EXPANSION_EMPTY:-d} ${EXPANSION_EMPTY+a} ${EXPANSION_EMPTY:+a}|\
+                     ${EXPANSION_UNSET:-${EXPANSION_SET}/x}";
+        let expected = "d value |d a |value/x";
+
+        let result = expand_parameters(input, &mut shell);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_expand_assign_default_value() {
+        let mut shell = Shell::new();
+        let input = "${EXPANSION_ASSIGNED:=first} ${EXPANSION_ASSIGNED:=second}";
+        let expected = "first first";
+
+        let result = expand_parameters(input, &mut shell);
+        assert_eq!(result, expected);
+        assert_eq!(shell.variables.get("EXPANSION_ASSIGNED"), Some("first"));
+        assert!(!shell.expansion_failed);
+
+        expand_parameters("${1:=a}", &mut shell);
+        assert!(shell.expansion_failed);
+    }
+
+    #[test]
+    fn test_expand_error_if_unset() {
+        let mut shell = Shell::new();
+        shell.variables.set("EXPANSION_SET", "value");
+
+        let result = expand_parameters("${EXPANSION_SET:?message}", &mut shell);
+        assert_eq!(result, "value");
+        assert!(!shell.expansion_failed);
+
+        expand_parameters("${EXPANSION_UNSET:?message}", &mut shell);
+        assert!(shell.expansion_failed);
+    }
+
+    #[test]
+    fn test_nounset_allows_operators_on_unset_parameters() {
+        let mut shell = Shell::new();
+        shell.options.nounset = true;
+        let input = "${1:-default} ${EXPANSION_UNSET-} ${EXPANSION_UNSET:+x}";
+        let expected = "default  ";
+
+        let result = expand_parameters(input, &mut shell);
+        assert_eq!(result, expected);
+        assert!(!shell.expansion_failed);
+
+        expand_parameters("${EXPANSION_UNSET}", &mut shell);
+        assert!(shell.expansion_failed);
+    }
+}
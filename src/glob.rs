@@ -0,0 +1,252 @@
+use std::fs;
+
+/// Whether `text` matches the shell pattern `pattern`, where `*` matches
+/// any string, `?` any character, `[...]` a set of characters and a
+/// backslash makes the next character literal.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*` if the rest fails to match.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, t));
+            p += 1;
+            continue;
+        }
+        if p < pattern.len() {
+            if let Some(next) = match_one(&pattern, p, text[t]) {
+                p = next;
+                t += 1;
+                continue;
+            }
+        }
+        match backtrack {
+            Some((star_next, star_text)) => {
+                p = star_next;
+                t = star_text + 1;
+                backtrack = Some((star_next, star_text + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Escapes the characters that are special in patterns.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Whether `pattern` has an unescaped `*`, `?` or `[...]`, so that it can
+/// match anything but itself.
+pub fn is_pattern(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' => return true,
+            '[' if chars.as_str().contains(']') => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Expands a pathname pattern to the paths that match it, in sorted order,
+/// or to nothing if none do. A leading `.` in a file name only matches a
+/// `.` written in the pattern.
+pub fn expand_paths(pattern: &str) -> Vec<String> {
+    let (mut paths, components) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    for component in components.split('/') {
+        let mut next = Vec::new();
+        for path in &paths {
+            let join = |name: &str| match path.is_empty() || path.ends_with('/') {
+                true => format!("{path}{name}"),
+                false => format!("{path}/{name}"),
+            };
+            if !is_pattern(component) {
+                next.push(join(&unescape(component)));
+                continue;
+            }
+
+            let directory = if path.is_empty() { "." } else { path.as_str() };
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| matches(component, name))
+                .collect();
+            names.sort();
+            next.extend(names.iter().map(|name| join(name)));
+        }
+        paths = next;
+    }
+
+    // Literal components were taken on trust, so check they exist.
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+    paths
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Matches the pattern element at `pattern[p]` against `c`, returning the
+/// index just past the element if it matches.
+fn match_one(pattern: &[char], p: usize, c: char) -> Option<usize> {
+    match pattern[p] {
+        '?' => Some(p + 1),
+        '[' => match match_bracket(pattern, p, c) {
+            Some((matched, next)) => matched.then_some(next),
+            // An unterminated bracket is an ordinary character.
+            None => (c == '[').then_some(p + 1),
+        },
+        '\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+/// Matches a `[...]` expression starting at `pattern[start]`, returning
+/// whether `c` is in the set and the index after the closing `]`, or
+/// `None` if the bracket is never closed.
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let mut index = start + 1;
+    let negated = matches!(pattern.get(index), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let current = *pattern.get(index)?;
+        if current == ']' && !first {
+            return Some((matched != negated, index + 1));
+        }
+        first = false;
+
+        if current == '[' && pattern.get(index + 1) == Some(&':') {
+            let rest: String = pattern[index + 2..].iter().collect();
+            if let Some(end) = rest.find(":]") {
+                matched |= in_class(&rest[..end], c);
+                index += 2 + rest[..end].chars().count() + 2;
+                continue;
+            }
+        }
+
+        let low = match current {
+            '\\' => {
+                index += 1;
+                *pattern.get(index)?
+            }
+            _ => current,
+        };
+        index += 1;
+
+        if pattern.get(index) == Some(&'-') && pattern.get(index + 1).is_some_and(|&c| c != ']') {
+            let high = match pattern[index + 1] {
+                '\\' => *pattern.get(index + 2)?,
+                high => high,
+            };
+            index += if pattern[index + 1] == '\\' { 3 } else { 2 };
+            matched |= (low..=high).contains(&c);
+        } else {
+            matched |= low == c;
+        }
+    }
+}
+
+fn in_class(class: &str, c: char) -> bool {
+    match class {
+        "alnum" => c.is_alphanumeric(),
+        "alpha" => c.is_alphabetic(),
+        "blank" => c == ' ' || c == '\t',
+        "digit" => c.is_ascii_digit(),
+        "lower" => c.is_lowercase(),
+        "upper" => c.is_uppercase(),
+        "space" => c.is_whitespace(),
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_stars_and_question_marks() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("a*b*c", "aXXbYbc"));
+        assert!(matches("?at", "cat"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("*", ""));
+        assert!(!matches("?", ""));
+    }
+
+    #[test]
+    fn test_matches_brackets_and_escapes() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[!a-c]x", "bx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[[:digit:]]*", "7up"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn test_expand_paths() {
+        let directory = std::env::temp_dir().join(format!("glob_test_{}", std::process::id()));
+        for name in ["b.rs", "a.rs", ".hidden.rs", "sub/c.rs"] {
+            let path = directory.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        let root = escape(directory.to_str().unwrap());
+
+        let result = expand_paths(&format!("{root}/*.rs"));
+        let expected = vec![
+            format!("{}/a.rs", directory.display()),
+            format!("{}/b.rs", directory.display()),
+        ];
+        assert_eq!(result, expected);
+        let result = expand_paths(&format!("{root}/*/c.rs"));
+        assert_eq!(result, vec![format!("{}/sub/c.rs", directory.display())]);
+        assert!(expand_paths(&format!("{root}/*.txt")).is_empty());
+        assert!(!is_pattern("\\*.rs") && !is_pattern("[") && is_pattern("[ab]"));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,677 @@
+use crate::utils::{error_message, home_path};
+use crate::variables::Variables;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, Write};
+use std::os::fd::{AsRawFd, RawFd};
+
+const DEFAULT_HISTORY_FILE: &str = "~/.shell_shell_history";
+const DEFAULT_HISTORY_SIZE: usize = 500;
+
+/// The history settings, read from `HISTFILE`, `HISTSIZE`, `HISTFILESIZE`
+/// and `HISTCONTROL` each time they're needed so changes apply at once.
+pub struct HistoryConfig {
+    pub file: Option<String>,
+    pub size: usize,
+    pub file_size: usize,
+    pub ignore_space: bool,
+    pub ignore_dups: bool,
+    pub erase_dups: bool,
+}
+
+impl HistoryConfig {
+    pub fn from_variables(variables: &Variables) -> Self {
+        let file = match variables.get("HISTFILE") {
+            Some("") => None,
+            Some(file) => Some(file.to_string()),
+            None => home_path(DEFAULT_HISTORY_FILE),
+        };
+        let size = variables
+            .get("HISTSIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_HISTORY_SIZE);
+        let file_size = variables
+            .get("HISTFILESIZE")
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(size);
+
+        let control = variables.get("HISTCONTROL").unwrap_or_default();
+        let has = |value: &str| control.split(':').any(|item| item == value);
+
+        HistoryConfig {
+            file,
+            size,
+            file_size,
+            ignore_space: has("ignorespace") || has("ignoreboth"),
+            ignore_dups: has("ignoredups") || has("ignoreboth"),
+            erase_dups: has("erasedups"),
+        }
+    }
+}
+
+/// The commands entered in this session, after whatever was loaded from the
+/// history file. New entries are appended to the file as they're added, so
+/// shells running side by side don't overwrite each other's history.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// How many entries have been dropped from the front, so that history
+    /// numbers stay stable as the list is trimmed.
+    offset: usize,
+}
+
+impl History {
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Looks up an entry by its history number, as shown by `history`.
+    fn get(&self, number: usize) -> Option<&str> {
+        number
+            .checked_sub(self.offset + 1)
+            .and_then(|index| self.entries.get(index))
+            .map(String::as_str)
+    }
+
+    fn last_number(&self) -> usize {
+        self.offset + self.entries.len()
+    }
+
+    /// The number the next command will get, for the prompt's `\!`.
+    pub fn next_number(&self) -> usize {
+        self.last_number() + 1
+    }
+
+    pub fn load(&mut self, config: &HistoryConfig) {
+        let Some(path) = &config.file else {
+            return;
+        };
+        let Ok(contents) = read_locked(path) else {
+            return;
+        };
+
+        self.entries.extend(decode_entries(&contents));
+        self.truncate(config.size);
+    }
+
+    pub fn add(&mut self, line: &str, config: &HistoryConfig) {
+        if !self.record(line, config) {
+            return;
+        }
+
+        if let Some(path) = &config.file {
+            if let Err(e) = append_locked(path, &encode_entries(&[line.to_string()])) {
+                eprintln!("history: {path}: {e}");
+            }
+        }
+    }
+
+    /// Keeps only the newest `HISTFILESIZE` entries in the history file.
+    pub fn trim_file(&self, config: &HistoryConfig) -> io::Result<()> {
+        let Some(path) = &config.file else {
+            return Ok(());
+        };
+
+        let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let _lock = FileLock::exclusive(&file)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let entries = decode_entries(&contents);
+        if entries.len() <= config.file_size {
+            return Ok(());
+        }
+
+        let kept = encode_entries(&entries[entries.len() - config.file_size..]);
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(kept.as_bytes())
+    }
+
+    /// Adds `line` to the in-memory list, returning whether it was kept.
+    fn record(&mut self, line: &str, config: &HistoryConfig) -> bool {
+        if line.trim().is_empty() || config.size == 0 {
+            return false;
+        }
+        if config.ignore_space && line.starts_with(' ') {
+            return false;
+        }
+        if config.ignore_dups && self.entries.last().is_some_and(|last| last == line) {
+            return false;
+        }
+        if config.erase_dups {
+            self.entries.retain(|entry| entry != line);
+        }
+
+        self.entries.push(line.to_string());
+        self.truncate(config.size);
+        true
+    }
+
+    fn truncate(&mut self, size: usize) {
+        if self.entries.len() > size {
+            let excess = self.entries.len() - size;
+            self.entries.drain(..excess);
+            self.offset += excess;
+        }
+    }
+}
+
+/// Implements `history [n]`, `history -c`, `history -d offset` and
+/// `history -w|-r [file]`.
+pub fn history(
+    args: &[String],
+    history: &mut History,
+    config: &HistoryConfig,
+) -> Result<String, String> {
+    let file_argument = |args: &[String]| {
+        args.get(1)
+            .cloned()
+            .or_else(|| config.file.clone())
+            .ok_or_else(|| "history: HISTFILE is not set\n".to_string())
+    };
+
+    match args.first().map(String::as_str) {
+        None => Ok(history.list(history.entries.len())),
+        Some("-c") => {
+            history.offset = 0;
+            history.entries.clear();
+            Ok(String::new())
+        }
+        Some("-d") => {
+            let offset = args
+                .get(1)
+                .ok_or_else(|| "history: -d: option requires an argument\n".to_string())?;
+            let number = match offset.parse::<isize>() {
+                Ok(number) if number < 0 => history.last_number() as isize + number + 1,
+                Ok(number) => number,
+                Err(_) => -1,
+            };
+            let index = usize::try_from(number)
+                .ok()
+                .and_then(|number| number.checked_sub(history.offset + 1))
+                .filter(|index| *index < history.entries.len())
+                .ok_or_else(|| format!("history: {offset}: history position out of range\n"))?;
+
+            history.entries.remove(index);
+            Ok(String::new())
+        }
+        Some("-w") => {
+            let path = file_argument(args)?;
+            write_locked(&path, &encode_entries(&history.entries))
+                .map_err(|e| format!("history: {}: {}\n", path, error_message(&e)))?;
+            Ok(String::new())
+        }
+        Some("-r") => {
+            let path = file_argument(args)?;
+            let contents = read_locked(&path)
+                .map_err(|e| format!("history: {}: {}\n", path, error_message(&e)))?;
+            history.entries.extend(decode_entries(&contents));
+            history.truncate(config.size);
+            Ok(String::new())
+        }
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) => Ok(history.list(count)),
+            Err(_) => Err(format!("history: {count}: numeric argument required\n")),
+        },
+    }
+}
+
+impl History {
+    /// The last `count` entries, numbered the way `history` prints them.
+    fn list(&self, count: usize) -> String {
+        let start = self.entries.len().saturating_sub(count);
+        self.entries[start..]
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| format!("{:>5}  {}\n", self.offset + start + index + 1, entry))
+            .collect()
+    }
+}
+
+/// Performs csh-style history expansion on `line`: `!!`, `!n`, `!-n`,
+/// `!prefix`, `!?substring?`, `!$`, `!^`, `!*`, word designators such as
+/// `!!:2` and the `^old^new` quick substitution. Returns `None` when the
+/// line has nothing to expand.
+pub fn expand(line: &str, history: &History) -> Result<Option<String>, String> {
+    if let Some(substitution) = line.strip_prefix('^') {
+        return quick_substitution(substitution, history).map(Some);
+    }
+    if !line.contains('!') {
+        return Ok(None);
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let escaped = index > 0 && chars[index - 1] == '\\';
+        if c == '\'' && !escaped && !in_double_quote {
+            in_single_quote = !in_single_quote;
+        }
+        if c == '"' && !escaped && !in_single_quote {
+            in_double_quote = !in_double_quote;
+        }
+        let starts_event = matches!(
+            chars.get(index + 1),
+            Some(next) if !next.is_whitespace() && !matches!(next, '=' | '(' | '"')
+        );
+        // `$!` and `${!name}` are parameter expansions.
+        let in_parameter = index > 0
+            && (chars[index - 1] == '$' || (index > 1 && chars[index - 2..index] == ['$', '{']));
+        if c != '!' || in_single_quote || escaped || in_parameter || !starts_event {
+            expanded.push(c);
+            index += 1;
+            continue;
+        }
+
+        let (text, next) = expand_event(&chars, index + 1, history)?;
+        expanded.push_str(&text);
+        changed = true;
+        index = next;
+    }
+
+    Ok(changed.then_some(expanded))
+}
+
+/// Expands the event and word designator starting just after a `!`,
+/// returning the text and the index after what was consumed.
+fn expand_event(chars: &[char], start: usize, history: &History) -> Result<(String, usize), String> {
+    let mut index = start;
+    let last = history.last_number();
+    let take_while = |index: &mut usize, predicate: &dyn Fn(char) -> bool| {
+        let begin = *index;
+        while *index < chars.len() && predicate(chars[*index]) {
+            *index += 1;
+        }
+        chars[begin..*index].iter().collect::<String>()
+    };
+
+    let mut designator: Option<String> = None;
+    let event = match chars[index] {
+        '!' => {
+            index += 1;
+            history.get(last)
+        }
+        '$' | '^' | '*' => {
+            designator = Some(chars[index].to_string());
+            index += 1;
+            history.get(last)
+        }
+        ':' => history.get(last),
+        '-' | '0'..='9' => {
+            let negative = chars[index] == '-';
+            if negative {
+                index += 1;
+            }
+            let digits = take_while(&mut index, &|c| c.is_ascii_digit());
+            let number = digits.parse::<usize>().unwrap_or(0);
+            let number = if negative {
+                (last + 1).checked_sub(number)
+            } else {
+                Some(number)
+            };
+            number.and_then(|number| history.get(number))
+        }
+        '?' => {
+            index += 1;
+            let substring = take_while(&mut index, &|c| c != '?');
+            if index < chars.len() {
+                index += 1;
+            }
+            history.entries.iter().rev().find(|entry| entry.contains(&substring)).map(String::as_str)
+        }
+        _ => {
+            let prefix = take_while(&mut index, &|c| !c.is_whitespace() && c != ':');
+            history.entries.iter().rev().find(|entry| entry.starts_with(&prefix)).map(String::as_str)
+        }
+    };
+
+    let spec: String = chars[start - 1..index].iter().collect();
+    let event = event.ok_or_else(|| format!("{spec}: event not found"))?;
+
+    if designator.is_none() && chars.get(index) == Some(&':') {
+        let begin = index + 1;
+        let designator_text = {
+            let mut end = begin;
+            while end < chars.len() && (chars[end].is_ascii_digit() || matches!(chars[end], '^' | '$' | '*' | '-')) {
+                end += 1;
+            }
+            index = end;
+            chars[begin..end].iter().collect::<String>()
+        };
+        designator = Some(designator_text);
+    }
+
+    let text = match designator {
+        None => event.to_string(),
+        Some(designator) => select_words(&split_words(event), &designator)
+            .ok_or_else(|| format!("{spec}: bad word specifier"))?,
+    };
+
+    Ok((text, index))
+}
+
+/// Applies a word designator (`n`, `^`, `$`, `*`, `n-m`, `n*`, `-m`) to the
+/// words of an event.
+fn select_words(words: &[String], designator: &str) -> Option<String> {
+    let last = words.len().checked_sub(1)?;
+    let position = |text: &str| match text {
+        "^" => Some(1),
+        "$" => Some(last),
+        _ => text.parse::<usize>().ok(),
+    };
+
+    let (first, end) = match designator {
+        "*" => (1, last),
+        "" => return None,
+        _ if designator.ends_with('*') => (position(&designator[..designator.len() - 1])?, last),
+        _ => match designator.split_once('-') {
+            Some(("", end)) => (0, position(end)?),
+            Some((first, "")) => (position(first)?, last.checked_sub(1)?),
+            Some((first, end)) => (position(first)?, position(end)?),
+            None => {
+                let position = position(designator)?;
+                (position, position)
+            }
+        },
+    };
+
+    if first > end {
+        return (designator.contains('*')).then(String::new);
+    }
+
+    Some(words.get(first..=end)?.join(" "))
+}
+
+/// `^old^new^` repeats the previous command with `old` replaced by `new`.
+/// The newline the line was read with is kept at the end.
+fn quick_substitution(substitution: &str, history: &History) -> Result<String, String> {
+    let (substitution, newline) = match substitution.strip_suffix('\n') {
+        Some(substitution) => (substitution, "\n"),
+        None => (substitution, ""),
+    };
+    let mut parts = substitution.splitn(3, '^');
+    let old = parts.next().unwrap_or_default();
+    let new = parts.next().unwrap_or_default();
+    let rest = parts.next().unwrap_or_default();
+
+    let previous = history
+        .get(history.last_number())
+        .ok_or_else(|| "!!: event not found".to_string())?;
+    if old.is_empty() || !previous.contains(old) {
+        return Err(format!("^{old}^{new}: substitution failed"));
+    }
+
+    Ok(format!("{}{}{}", previous.replacen(old, new, 1), rest, newline))
+}
+
+/// Splits an event into words, keeping quoted strings together.
+fn split_words(event: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut curr = String::new();
+    let mut quote: Option<char> = None;
+    for c in event.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() => {
+                if !curr.is_empty() {
+                    words.push(std::mem::take(&mut curr));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        curr.push(c);
+    }
+    if !curr.is_empty() {
+        words.push(curr);
+    }
+
+    words
+}
+
+/// Holds a `flock` on a file until dropped. It must not outlive the file.
+struct FileLock {
+    fd: RawFd,
+}
+
+impl FileLock {
+    fn exclusive(file: &File) -> io::Result<Self> {
+        FileLock::acquire(file, libc::LOCK_EX)
+    }
+
+    fn shared(file: &File) -> io::Result<Self> {
+        FileLock::acquire(file, libc::LOCK_SH)
+    }
+
+    fn acquire(file: &File, operation: libc::c_int) -> io::Result<Self> {
+        let fd = file.as_raw_fd();
+        if unsafe { libc::flock(fd, operation) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(FileLock { fd })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        unsafe {
+            libc::flock(self.fd, libc::LOCK_UN);
+        }
+    }
+}
+
+fn read_locked(path: &str) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let _lock = FileLock::shared(&file)?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+fn append_locked(path: &str, text: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let _lock = FileLock::exclusive(&file)?;
+
+    file.write_all(text.as_bytes())
+}
+
+fn write_locked(path: &str, text: &str) -> io::Result<()> {
+    // Truncated only once locked, so readers never see it half written.
+    let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(path)?;
+    let _lock = FileLock::exclusive(&file)?;
+
+    file.set_len(0)?;
+    file.write_all(text.as_bytes())
+}
+
+/// One entry per line, with the lines of a multi-line command joined by a
+/// trailing backslash. Backslashes in the entries themselves are doubled,
+/// so that one at the end of an entry isn't mistaken for a continuation.
+fn encode_entries(entries: &[String]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{}\n", entry.replace('\\', "\\\\").replace('\n', "\\\n")))
+        .collect()
+}
+
+fn decode_entries(contents: &str) -> Vec<String> {
+    let mut entries = Vec::new();
+    let mut curr = String::new();
+    for line in contents.lines() {
+        let trailing = line.len() - line.trim_end_matches('\\').len();
+        match trailing % 2 == 1 {
+            true => {
+                curr.push_str(&unescape_backslashes(&line[..line.len() - 1]));
+                curr.push('\n');
+            }
+            false => {
+                curr.push_str(&unescape_backslashes(line));
+                entries.push(std::mem::take(&mut curr));
+            }
+        }
+    }
+    if !curr.is_empty() {
+        entries.push(curr);
+    }
+
+    entries
+}
+
+/// Undoes the doubling of backslashes. A lone backslash, as written before
+/// they were doubled, is kept.
+fn unescape_backslashes(line: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek() == Some(&'\\') {
+            chars.next();
+        }
+        unescaped.push(c);
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn config() -> HistoryConfig {
+        HistoryConfig {
+            file: None,
+            size: 3,
+            file_size: 3,
+            ignore_space: true,
+            ignore_dups: true,
+            erase_dups: false,
+        }
+    }
+
+    #[test]
+    fn test_history_respects_histcontrol_and_histsize() {
+        let mut history = History::default();
+        for line in ["ls", "ls", " secret", "pwd", "cd /tmp", "echo hi"] {
+            history.add(line, &config());
+        }
+
+        let expected = vec!["pwd".to_string(), "cd /tmp".to_string(), "echo hi".to_string()];
+        assert_eq!(history.entries(), expected);
+    }
+
+    fn history_of(entries: &[&str]) -> History {
+        History {
+            entries: entries.iter().map(|entry| entry.to_string()).collect(),
+            offset: 10,
+        }
+    }
+
+    #[test]
+    fn test_expand_event_designators() {
+        let history = history_of(&["ls -la /tmp", "cat a.txt b.txt", "echo hi"]);
+
+        assert_eq!(expand("sudo !!", &history), Ok(Some("sudo echo hi".to_string())));
+        assert_eq!(expand("!11", &history), Ok(Some("ls -la /tmp".to_string())));
+        assert_eq!(expand("!-2 x", &history), Ok(Some("cat a.txt b.txt x".to_string())));
+        assert_eq!(expand("!ca", &history), Ok(Some("cat a.txt b.txt".to_string())));
+        assert_eq!(expand("!?la?", &history), Ok(Some("ls -la /tmp".to_string())));
+        assert_eq!(expand("!nope", &history), Err("!nope: event not found".to_string()));
+    }
+
+    #[test]
+    fn test_expand_word_designators() {
+        let history = history_of(&["cat a.txt 'b c.txt'", "echo x y z"]);
+
+        assert_eq!(expand("vim !$", &history), Ok(Some("vim z".to_string())));
+        assert_eq!(expand("ls !-2:2", &history), Ok(Some("ls 'b c.txt'".to_string())));
+        assert_eq!(expand("echo !*", &history), Ok(Some("echo x y z".to_string())));
+        assert_eq!(expand("echo !!:1-2", &history), Ok(Some("echo x y".to_string())));
+        assert_eq!(expand("echo !^", &history), Ok(Some("echo x".to_string())));
+    }
+
+    #[test]
+    fn test_expand_leaves_quoted_and_lone_bangs() {
+        let history = history_of(&["ls"]);
+
+        assert_eq!(expand("echo '!!' hi! != \\!!", &history), Ok(None));
+        assert_eq!(expand("echo $! ${!name} ${!a[@]}", &history), Ok(None));
+    }
+
+    #[test]
+    fn test_expand_inside_double_quotes() {
+        let history = history_of(&["ls"]);
+
+        let result = expand(r#"echo "it's !!""#, &history);
+        assert_eq!(result, Ok(Some(r#"echo "it's ls""#.to_string())));
+        let result = expand(r#"echo "'" '"!!'"#, &history);
+        assert_eq!(result, Ok(None));
+    }
+
+    #[test]
+    fn test_quick_substitution() {
+        let history = history_of(&["grep foo file.txt"]);
+
+        let result = expand("^foo^bar\n", &history);
+        assert_eq!(result, Ok(Some("grep bar file.txt\n".to_string())));
+        let result = expand("^file^dir^ -v\n", &history);
+        assert_eq!(result, Ok(Some("grep foo dir.txt -v\n".to_string())));
+    }
+
+    #[test]
+    fn test_history_delete_uses_history_numbers() {
+        let mut history = history_of(&["a", "b", "c"]);
+        let args = vec!["-d".to_string(), "12".to_string()];
+
+        super::history(&args, &mut history, &config()).unwrap();
+
+        assert_eq!(history.entries(), vec!["a".to_string(), "c".to_string()]);
+        assert_eq!(history.list(1), "   12  c\n");
+    }
+
+    #[test]
+    fn test_encode_and_decode_multi_line_entries() {
+        let entries = vec!["greet() {\n  echo hi\n}".to_string(), "greet".to_string()];
+
+        let result = decode_entries(&encode_entries(&entries));
+        assert_eq!(result, entries);
+    }
+
+    #[test]
+    fn test_encode_and_decode_trailing_backslashes() {
+        let entries = vec![
+            "echo a\\".to_string(),
+            "echo b\\\\\n\\".to_string(),
+            "printf '%s\\n' c".to_string(),
+        ];
+
+        let result = decode_entries(&encode_entries(&entries));
+        assert_eq!(result, entries);
+    }
+
+    #[test]
+    fn test_history_write_replaces_file() {
+        let path = std::env::temp_dir().join(format!("history_test_{}", std::process::id()));
+        let file = path.to_str().unwrap().to_string();
+        fs::write(&path, "old\nentries\n").unwrap();
+        let mut history = history_of(&["a", "b"]);
+
+        let args = vec!["-w".to_string(), file.clone()];
+        super::history(&args, &mut history, &config()).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+use crate::signals;
+use std::io;
+
+pub enum InputLine {
+    Line(String),
+    Interrupted,
+    Eof,
+}
+
+/// Reads one line from stdin a byte at a time, straight from the file
+/// descriptor, so nothing past the newline is buffered away from children.
+pub fn read_line() -> InputLine {
+    signals::take_interrupt();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    loop {
+        let mut byte = 0u8;
+        let count = unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+            )
+        };
+
+        match count {
+            1 if byte == b'\n' => break,
+            1 => bytes.push(byte),
+            0 if bytes.is_empty() => return InputLine::Eof,
+            0 => break,
+            _ => {
+                if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    return InputLine::Eof;
+                }
+                if signals::take_interrupt() {
+                    return InputLine::Interrupted;
+                }
+            }
+        }
+    }
+
+    InputLine::Line(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -1,24 +1,313 @@
+mod alias;
+mod arithmetic;
 mod command;
+mod completion;
+mod conditional;
+mod declare;
+mod dirs;
+mod echo;
+mod editor;
+mod exec;
+mod expansion;
+mod glob;
+mod history;
+mod input;
+mod options;
 mod parser;
+mod printf;
+mod prompt;
+mod read;
+mod shell;
+mod signals;
+mod terminal;
+mod trap;
 mod utils;
+mod variables;
 
-use crate::command::Executable;
-use std::io;
-use std::io::{BufRead, Write};
+use crate::editor::Editor;
+use crate::input::InputLine;
+use crate::shell::Shell;
+use crate::utils::error_message;
+use std::io::{IsTerminal, Write};
+use std::{env, fs, io};
+
+/// How many consecutive EOFs `ignoreeof` swallows before giving up.
+const IGNOREEOF_LIMIT: u32 = 10;
+
+const SYSTEM_PROFILE: &str = "/etc/profile";
+const USER_PROFILE: &str = "~/.shell_shell_profile";
+const USER_RC_FILE: &str = "~/.shell_shellrc";
+
+/// Where the shell takes its commands from.
+enum Source {
+    Stdin,
+    CommandString(String),
+    Script(String),
+}
+
+struct Invocation {
+    source: Source,
+    force_interactive: bool,
+    login: bool,
+    posix: bool,
+    read_rc: bool,
+    read_profile: bool,
+    rc_file: Option<String>,
+    script_name: Option<String>,
+    positional: Vec<String>,
+    /// Options for `set`, such as `-e` or `-o pipefail`.
+    options: Vec<String>,
+}
+
+/// Parses `[long options] [-i] [-l] [-s] [-Cefnuvx] [-o option]
+/// [-c string [name [args...]]] [script [args...]]`.
+fn parse_arguments(args: &[String]) -> Result<Invocation, String> {
+    let mut invocation = Invocation {
+        source: Source::Stdin,
+        force_interactive: false,
+        login: false,
+        posix: false,
+        read_rc: true,
+        read_profile: true,
+        rc_file: None,
+        script_name: None,
+        positional: Vec::new(),
+        options: Vec::new(),
+    };
+    let mut read_stdin = false;
+    let mut command_string = false;
+
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        match arg.as_str() {
+            "--login" => invocation.login = true,
+            "--norc" => invocation.read_rc = false,
+            "--noprofile" => invocation.read_profile = false,
+            "--posix" => invocation.posix = true,
+            "--rcfile" | "--init-file" => {
+                index += 1;
+                let file = args
+                    .get(index)
+                    .ok_or_else(|| format!("{arg}: option requires an argument"))?;
+                invocation.rc_file = Some(file.clone());
+            }
+            "-c" => command_string = true,
+            "-i" => invocation.force_interactive = true,
+            "-l" => invocation.login = true,
+            "-s" => read_stdin = true,
+            "-o" | "+o" => {
+                index += 1;
+                let name = args
+                    .get(index)
+                    .ok_or_else(|| format!("{arg}: option requires an argument"))?;
+                invocation.options.extend([arg.clone(), name.clone()]);
+            }
+            _ if arg.len() > 1 && arg.starts_with('+') && arg[1..].chars().all(is_set_flag) => {
+                invocation.options.push(arg.clone());
+            }
+            // Single-letter options may be combined, as in `-ec`.
+            _ if arg.len() > 1
+                && arg.starts_with('-')
+                && arg[1..].chars().all(|c| is_set_flag(c) || "cils".contains(c)) =>
+            {
+                for letter in arg[1..].chars() {
+                    match letter {
+                        'c' => command_string = true,
+                        'i' => invocation.force_interactive = true,
+                        'l' => invocation.login = true,
+                        's' => read_stdin = true,
+                        _ => invocation.options.push(format!("-{letter}")),
+                    }
+                }
+            }
+            "-" | "--" => {
+                index += 1;
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("{arg}: invalid option"));
+            }
+            _ => break,
+        }
+        index += 1;
+    }
+    let mut operands = args[index.min(args.len())..].iter().cloned();
+
+    if command_string {
+        let string = operands
+            .next()
+            .ok_or_else(|| "-c: option requires an argument".to_string())?;
+        invocation.source = Source::CommandString(string);
+        invocation.script_name = operands.next();
+    } else if !read_stdin {
+        if let Some(script) = operands.next() {
+            invocation.script_name = Some(script.clone());
+            invocation.source = Source::Script(script);
+        }
+    }
+    invocation.positional = operands.collect();
+
+    Ok(invocation)
+}
+
+/// Whether `-letter` is also an option of `set`.
+fn is_set_flag(letter: char) -> bool {
+    "Cefnuvx".contains(letter)
+}
 
 fn main() {
-    loop {
-        display_command_prompt();
+    let args: Vec<String> = env::args().collect();
+    let invocation = match parse_arguments(&args[1..]) {
+        Ok(invocation) => invocation,
+        Err(message) => {
+            eprintln!("shell_shell: {message}");
+            std::process::exit(2);
+        }
+    };
 
-        let stdin = io::stdin().lock();
-        let line = stdin.lines().next().unwrap().unwrap();
-        let command = parser::parse_command(&line);
+    let arg0 = args.first().cloned().unwrap_or_default();
+    let login = invocation.login || arg0.starts_with('-');
+    let posix = invocation.posix || arg0.trim_start_matches('-').rsplit('/').next() == Some("sh");
 
-        command.execute();
+    let mut shell = Shell::new();
+    dirs::initialize_pwd(&mut shell);
+    shell.script_name = invocation.script_name.clone().unwrap_or(arg0);
+    shell.positional = invocation.positional.clone();
+    if let Err(message) = options::set(&invocation.options, &mut shell.options, &mut Vec::new()) {
+        eprint!("shell_shell: {message}");
+        std::process::exit(2);
+    }
+    if let Source::Stdin = invocation.source {
+        shell.interactive = invocation.force_interactive || io::stdin().is_terminal();
+        shell.options.histexpand = shell.interactive;
+    }
+    signals::init_shell_signals(shell.interactive);
+
+    source_startup_files(&mut shell, &invocation, login, posix);
+    if shell.interactive {
+        shell.load_history();
+    }
+
+    let status = match invocation.source {
+        Source::CommandString(string) => shell.run_script(&string),
+        Source::Script(path) => match fs::read_to_string(&path) {
+            Ok(script) => shell.run_script(&script),
+            Err(e) => {
+                eprintln!("shell_shell: {}: {}", path, error_message(&e));
+                127
+            }
+        },
+        Source::Stdin => read_commands(&mut shell),
+    };
+
+    shell.exit(status);
+}
+
+/// Login shells read the profiles; other interactive shells read the rc
+/// file, or whatever `ENV` names in POSIX mode.
+fn source_startup_files(shell: &mut Shell, invocation: &Invocation, login: bool, posix: bool) {
+    if login {
+        if invocation.read_profile {
+            shell.source_startup_file(SYSTEM_PROFILE);
+            shell.source_startup_file(USER_PROFILE);
+        }
+    } else if shell.interactive && posix {
+        if let Some(env_file) = shell.variables.get("ENV").map(str::to_string) {
+            let env_file = expansion::expand_parameters(&env_file, shell);
+            shell.source_startup_file(&env_file);
+        }
+    } else if shell.interactive && invocation.read_rc {
+        let rc_file = invocation.rc_file.as_deref().unwrap_or(USER_RC_FILE);
+        shell.source_startup_file(rc_file);
     }
 }
 
-fn display_command_prompt() {
-    print!("$ ");
-    io::stdout().flush().unwrap();
+/// Reads and runs commands from stdin until EOF, prompting only when the
+/// shell is interactive.
+fn read_commands(shell: &mut Shell) -> i32 {
+    let mut eof_count = 0;
+    let mut pending = String::new();
+    let mut editor = (shell.interactive && io::stdin().is_terminal()).then(Editor::new);
+
+    loop {
+        if shell.interactive && pending.is_empty() {
+            shell.run_prompt_hooks();
+        }
+        let input = match editor.as_mut() {
+            Some(editor) => {
+                let prompt = prompt::command_prompt(shell, pending.is_empty());
+                editor.read_line(&prompt, shell)
+            }
+            None => {
+                if shell.interactive {
+                    let prompt = prompt::command_prompt(shell, pending.is_empty());
+                    print!("{}", prompt::strip_markers(&prompt));
+                    io::stdout().flush().unwrap();
+                }
+                input::read_line()
+            }
+        };
+
+        let line = match input {
+            InputLine::Line(line) => line,
+            InputLine::Interrupted => {
+                if editor.is_none() {
+                    println!();
+                }
+                pending.clear();
+                shell.run_pending_traps();
+                continue;
+            }
+            InputLine::Eof if !pending.is_empty() => {
+                eprintln!("{}: syntax error: unexpected end of file", shell.script_name);
+                pending.clear();
+                shell.last_status = 2;
+                continue;
+            }
+            InputLine::Eof => {
+                eof_count += 1;
+                if shell.interactive && shell.options.ignoreeof && eof_count < IGNOREEOF_LIMIT {
+                    println!("\nUse \"exit\" to leave the shell.");
+                    continue;
+                }
+                break;
+            }
+        };
+        eof_count = 0;
+        if shell.options.verbose {
+            eprintln!("{line}");
+        }
+
+        pending.push_str(&line);
+        pending.push('\n');
+        if parser::is_complete(&pending) {
+            if shell.options.histexpand {
+                match history::expand(&pending, &shell.history) {
+                    Ok(Some(expanded)) => {
+                        print!("{expanded}");
+                        pending = expanded;
+                    }
+                    Ok(None) => {}
+                    Err(message) => {
+                        eprintln!("{}: {}", shell.script_name, message);
+                        pending.clear();
+                        shell.last_status = 1;
+                        continue;
+                    }
+                }
+            }
+            if shell.interactive {
+                let line = pending.trim_end_matches('\n').to_string();
+                shell.add_history(&line);
+                shell.run_hook("preexec", &[line]);
+            }
+            shell.run_source(&pending);
+            pending.clear();
+        }
+    }
+
+    if shell.interactive {
+        println!("exit");
+    }
+    shell.last_status
 }
@@ -0,0 +1,191 @@
+/// The single-letter flags of `set` and the options they stand for.
+const SHORT_FLAGS: &[(char, &str)] = &[
+    ('C', "noclobber"),
+    ('e', "errexit"),
+    ('f', "noglob"),
+    ('n', "noexec"),
+    ('u', "nounset"),
+    ('v', "verbose"),
+    ('x', "xtrace"),
+];
+
+/// Options toggled with `set -o name` / `set +o name`.
+#[derive(Debug, Default)]
+pub struct ShellOptions {
+    pub ignoreeof: bool,
+    /// `!` history expansion, on by default in interactive shells.
+    pub histexpand: bool,
+    /// Makes `echo` decode backslash escapes without `-e`, as XSI does.
+    pub xpg_echo: bool,
+    /// `-e`: exit as soon as a command fails, unless its status is being
+    /// tested by `&&`, `||` or `!`.
+    pub errexit: bool,
+    /// `-u`: expanding an unset variable is an error.
+    pub nounset: bool,
+    /// `-x`: print each command, expanded and prefixed by `PS4`, to stderr
+    /// before running it.
+    pub xtrace: bool,
+    /// `-f`: no pathname expansion.
+    pub noglob: bool,
+    /// `-n`: read commands and check their syntax without running them.
+    pub noexec: bool,
+    /// `-v`: print input lines to stderr as they are read.
+    pub verbose: bool,
+    /// A pipeline fails with its last failing command, not just its last.
+    pub pipefail: bool,
+    /// `-C`: `>` refuses to overwrite existing files; `>|` still does.
+    pub noclobber: bool,
+}
+
+impl ShellOptions {
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "histexpand" => Some(&mut self.histexpand),
+            "ignoreeof" => Some(&mut self.ignoreeof),
+            "noclobber" => Some(&mut self.noclobber),
+            "noexec" => Some(&mut self.noexec),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "verbose" => Some(&mut self.verbose),
+            "xpg_echo" => Some(&mut self.xpg_echo),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    fn flags(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("errexit", self.errexit),
+            ("histexpand", self.histexpand),
+            ("ignoreeof", self.ignoreeof),
+            ("noclobber", self.noclobber),
+            ("noexec", self.noexec),
+            ("noglob", self.noglob),
+            ("nounset", self.nounset),
+            ("pipefail", self.pipefail),
+            ("verbose", self.verbose),
+            ("xpg_echo", self.xpg_echo),
+            ("xtrace", self.xtrace),
+        ]
+    }
+
+    /// The letters of the single-letter options that are on, as `$-`
+    /// shows them.
+    pub fn short_flags(&self) -> String {
+        let flags = self.flags();
+        SHORT_FLAGS
+            .iter()
+            .filter(|(_, name)| flags.contains(&(name, true)))
+            .map(|(letter, _)| *letter)
+            .collect()
+    }
+
+    fn list(&self) -> String {
+        self.flags()
+            .into_iter()
+            .map(|(name, on)| format!("{:<15}\t{}\n", name, if on { "on" } else { "off" }))
+            .collect()
+    }
+
+    /// Lists the options as commands that would restore them.
+    fn list_as_commands(&self) -> String {
+        self.flags()
+            .into_iter()
+            .map(|(name, on)| format!("set {}o {}\n", if on { '-' } else { '+' }, name))
+            .collect()
+    }
+}
+
+/// Implements `set [-Cefnuvx] [-o name] [--] [args...]`, with `+` turning
+/// options off. `-o` or `+o` without a name lists the options, and other
+/// arguments replace the positional parameters.
+pub fn set(
+    args: &[String],
+    options: &mut ShellOptions,
+    positional: &mut Vec<String>,
+) -> Result<String, String> {
+    let mut output = String::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let (enable, letters) = match arg.as_str() {
+            "--" => {
+                *positional = args.cloned().collect();
+                break;
+            }
+            // `set -` turns off `-x` and `-v`, and ends the options.
+            "-" => {
+                options.xtrace = false;
+                options.verbose = false;
+                *positional = args.cloned().collect();
+                break;
+            }
+            _ if arg.len() > 1 && arg.starts_with(['-', '+']) => {
+                (arg.starts_with('-'), &arg[1..])
+            }
+            _ => {
+                *positional = std::iter::once(arg).chain(args).cloned().collect();
+                break;
+            }
+        };
+
+        for letter in letters.chars() {
+            if letter != 'o' {
+                let name = SHORT_FLAGS
+                    .iter()
+                    .find(|(short, _)| *short == letter)
+                    .map(|(_, name)| *name);
+                match name.and_then(|name| options.flag_mut(name)) {
+                    Some(flag) => *flag = enable,
+                    None => return Err(format!("set: {}{letter}: invalid option\n", &arg[..1])),
+                }
+                continue;
+            }
+
+            match args.next() {
+                Some(name) => match options.flag_mut(name) {
+                    Some(flag) => *flag = enable,
+                    None => return Err(format!("set: {name}: invalid option name\n")),
+                },
+                None if enable => output.push_str(&options.list()),
+                None => output.push_str(&options.list_as_commands()),
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_set_combined_flags_and_names() {
+        let mut options = ShellOptions::default();
+        let mut positional = Vec::new();
+
+        let result = set(&args("-euo pipefail +e -x a b"), &mut options, &mut positional);
+        assert_eq!(result, Ok(String::new()));
+        assert!(!options.errexit && options.nounset && options.pipefail && options.xtrace);
+        assert_eq!(positional, vec!["a", "b"]);
+        assert_eq!(options.short_flags(), "ux");
+    }
+
+    #[test]
+    fn test_set_rejects_unknown_options() {
+        let mut options = ShellOptions::default();
+        let mut positional = Vec::new();
+
+        let result = set(&args("-ez"), &mut options, &mut positional);
+        assert_eq!(result, Err("set: -z: invalid option\n".to_string()));
+        let result = set(&args("+o nosuch"), &mut options, &mut positional);
+        assert_eq!(result, Err("set: nosuch: invalid option name\n".to_string()));
+    }
+}
@@ -1,36 +1,653 @@
 use crate::command::{Command, Redirection, OutputChannel, RedirectionKind};
-use crate::utils::expand_home_path;
+use crate::conditional::{self, ConditionalWord};
+use crate::declare::Operand;
+use crate::expansion::{
+    expand_backquote, expand_dollar, expand_word, read_braced, read_substitution, Expansion,
+};
+use crate::glob;
+use crate::shell::Shell;
+use crate::utils::{check_redirection, error_message, expand_home_path, quote_if_needed};
+use crate::variables::{is_valid_name, parse_assignment, AssignedValue, Assignment};
 
-const REDIRECT_OPERATORS: [&str; 6] = [">", "1>", "2>", ">>", "1>>", "2>>"];
+const REDIRECT_OPERATORS: [&str; 9] = [">", "1>", "2>", ">|", "1>|", "2>|", ">>", "1>>", "2>>"];
 
-pub fn parse_command(line: &str) -> Command {
-    let tokens = tokenize(line);
+pub fn parse_command(line: &str, shell: &mut Shell) -> Command {
+    if let Some((name, body)) = parse_function_definition(line) {
+        return Command::DefineFunction { name, body };
+    }
+
+    // `[[` is a keyword, so its words are split and expanded its own way.
+    if let Some(("[[", rest)) = line.trim_start().split_once(char::is_whitespace) {
+        let words: Vec<ConditionalWord> = conditional::split_words(rest)
+            .iter()
+            .map(|word| ConditionalWord::expand(word, shell))
+            .collect();
+        if shell.options.xtrace {
+            let texts: Vec<String> = words.iter().map(|word| word.text.clone()).collect();
+            shell.trace(&format!("[[ {}", texts.join(" ")));
+        }
+        return Command::Conditional { words };
+    }
+
+    // Assignments are expanded without field splitting, and may assign
+    // `(...)` lists to arrays, so they are recognised before tokenizing.
+    let words = split_raw_words(line);
+    if !words.is_empty() && words.iter().all(|word| assignment_parts(word).is_some()) {
+        let assignments: Vec<Assignment> = words
+            .iter()
+            .filter_map(|word| expand_assignment(word, shell))
+            .collect();
+        if shell.options.xtrace {
+            for assignment in &assignments {
+                shell.trace(&trace_assignment(assignment));
+            }
+        }
+        return Command::Assign { assignments };
+    }
+    if let Some("declare" | "typeset") = words.first().map(String::as_str) {
+        if !shell.functions.contains_key(&words[0]) {
+            return parse_declaration(&words[1..], shell);
+        }
+    }
+
+    let mut tokens = tokenize_words(line, shell);
+    // `exec` applies its redirections to the shell itself, so it gets them
+    // all as arguments, along with the quoting that tells them apart.
+    if tokens.first().is_some_and(|token| token.text == "exec")
+        && !shell.functions.contains_key("exec")
+    {
+        let args = tokens.split_off(1);
+        if shell.options.xtrace {
+            let words: Vec<String> =
+                tokens.iter().chain(&args).map(|token| token.text.clone()).collect();
+            shell.trace(&quote_words(&words));
+        }
+        return Command::Exec { args };
+    }
+
+    let tokens = tokens.into_iter().map(|token| token.text).collect();
     let (command_tokens, redirection_tokens) = split_tokens(tokens);
+    if shell.options.xtrace && !command_tokens.is_empty() {
+        shell.trace(&quote_words(&command_tokens));
+    }
+
+    let redirection_command: Option<Redirection> =
+        parse_redirection(&redirection_tokens, shell.options.noclobber);
+    if let Some(error) = redirection_command.as_ref().and_then(redirection_error) {
+        return error;
+    }
+    if let Some(name) = command_tokens.first() {
+        if shell.functions.contains_key(name) {
+            return Command::Function {
+                name: name.clone(),
+                args: command_tokens[1..].to_vec(),
+                redirection: redirection_command,
+            };
+        }
+    }
 
-    let redirection_command: Option<Redirection> = parse_redirection(&redirection_tokens);
     parse(&command_tokens, redirection_command)
 }
 
-fn tokenize(input: &str) -> Vec<String> {
-    let input = input.trim();
+/// How a pipeline in an `&&`/`||` list is joined to the one before it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Commands joined by `|`, the status of which `!` inverts.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pipeline {
+    pub negated: bool,
+    pub commands: Vec<String>,
+}
+
+/// Splits a command into the pipelines of an `&&`/`||` list. Quotes,
+/// substitutions, `${...}`, brace groups and `[[ ]]` are never split, so
+/// their `|`, `&&` and `||` are left alone.
+pub fn split_list(line: &str) -> Result<Vec<(Option<Connector>, Pipeline)>, String> {
+    let mut list = Vec::new();
+    let mut connector = None;
+    let mut commands = Vec::new();
+    let mut curr = String::new();
+
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut in_conditional = false;
+    let mut brace_depth = 0;
+    let mut at_word_start = true;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let ends_word = matches!(chars.peek(), None | Some(' ' | '\t' | '\n' | ';'));
+        let separator = match c {
+            '\\' if !in_single_quote => {
+                curr.push(c);
+                curr.extend(chars.next());
+                at_word_start = false;
+                continue;
+            }
+            '\'' if !in_double_quote => {
+                in_single_quote = !in_single_quote;
+                None
+            }
+            '"' if !in_single_quote => {
+                in_double_quote = !in_double_quote;
+                None
+            }
+            _ if in_single_quote => None,
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                curr.push_str("$(");
+                curr.extend(read_substitution(&mut chars));
+                curr.push(')');
+                at_word_start = false;
+                continue;
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                curr.push_str("${");
+                curr.extend(read_braced(&mut chars));
+                curr.push('}');
+                at_word_start = false;
+                continue;
+            }
+            '`' => {
+                curr.push(c);
+                while let Some(next) = chars.next() {
+                    curr.push(next);
+                    if next == '\\' {
+                        curr.extend(chars.next());
+                    } else if next == '`' {
+                        break;
+                    }
+                }
+                at_word_start = false;
+                continue;
+            }
+            _ if in_double_quote => None,
+            '#' if at_word_start => {
+                curr.push(c);
+                curr.extend(chars.by_ref());
+                break;
+            }
+            '[' if at_word_start && chars.peek() == Some(&'[') => {
+                in_conditional = true;
+                None
+            }
+            ']' if in_conditional && chars.peek() == Some(&']') => {
+                chars.next();
+                curr.push_str("]]");
+                in_conditional = false;
+                at_word_start = false;
+                continue;
+            }
+            '{' if at_word_start && ends_word => {
+                brace_depth += 1;
+                None
+            }
+            '}' if at_word_start && ends_word && brace_depth > 0 => {
+                brace_depth -= 1;
+                None
+            }
+            _ if in_conditional || brace_depth > 0 => None,
+            '|' if curr.ends_with('>') => None,
+            '|' if chars.peek() == Some(&'|') => Some("||"),
+            '|' => Some("|"),
+            '&' if chars.peek() == Some(&'&') => Some("&&"),
+            _ => None,
+        };
+
+        let Some(separator) = separator else {
+            curr.push(c);
+            at_word_start = !in_single_quote && !in_double_quote && c.is_whitespace();
+            continue;
+        };
+        if separator.len() == 2 {
+            chars.next();
+        }
+
+        let command = std::mem::take(&mut curr);
+        if command.trim().is_empty() {
+            return Err(format!("syntax error near unexpected token `{separator}'"));
+        }
+        commands.push(command.trim().to_string());
+        if separator != "|" {
+            list.push((connector, pipeline(std::mem::take(&mut commands))));
+            connector = Some(match separator {
+                "&&" => Connector::And,
+                _ => Connector::Or,
+            });
+        }
+        at_word_start = true;
+    }
+
+    if curr.trim().is_empty() {
+        return match commands.is_empty() && list.is_empty() {
+            true => Ok(Vec::new()),
+            false => Err("syntax error: unexpected end of file".to_string()),
+        };
+    }
+    commands.push(curr.trim().to_string());
+    list.push((connector, pipeline(commands)));
+
+    Ok(list)
+}
+
+/// Makes a pipeline of `commands`, taking a leading `!` off the first.
+fn pipeline(mut commands: Vec<String>) -> Pipeline {
+    let negated = match commands[0].strip_prefix('!') {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+            commands[0] = rest.trim_start().to_string();
+            true
+        }
+        _ => false,
+    };
+
+    Pipeline { negated, commands }
+}
 
-    let mut tokens: Vec<String> = Vec::new();
+/// Splits source text into commands at unquoted `;` and newlines. Brace
+/// groups such as function bodies are kept whole, comments are dropped and
+/// backslash-newline pairs are joined.
+pub fn split_commands(source: &str) -> Vec<String> {
+    scan(source).0
+}
+
+/// Whether `source` can run as is, or still has an open quote, brace or
+/// trailing backslash and needs more lines.
+pub fn is_complete(source: &str) -> bool {
+    scan(source).1
+}
+
+fn scan(source: &str) -> (Vec<String>, bool) {
+    let mut commands: Vec<String> = Vec::new();
     let mut curr = String::new();
 
     let mut in_single_quote = false;
     let mut in_double_quote = false;
     let mut to_escape = false;
-    for c in input.chars() {
+    let mut in_comment = false;
+    let mut brace_depth = 0;
+    let mut at_word_start = true;
+    let mut unterminated = false;
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_comment && c != '\n' {
+            continue;
+        }
+        in_comment = false;
+
         if to_escape {
-            if in_double_quote && (c == '"' || c == '\\' || c == '`' || c == '$') {
+            to_escape = false;
+            if c == '\n' {
+                curr.pop();
+            } else {
+                curr.push(c);
+            }
+            at_word_start = false;
+            continue;
+        }
+
+        let ends_word = matches!(chars.peek(), None | Some(';') | Some(' ' | '\t' | '\n'));
+        match c {
+            '\\' if !in_single_quote => to_escape = true,
+            '$' if !in_single_quote && chars.peek() == Some(&'(') => {
+                chars.next();
+                curr.push_str("$(");
+                match read_substitution(&mut chars) {
+                    Some(command) => {
+                        curr.push_str(&command);
+                        curr.push(')');
+                    }
+                    None => unterminated = true,
+                }
+                at_word_start = false;
+                continue;
+            }
+            '$' if !in_single_quote && chars.peek() == Some(&'{') => {
+                chars.next();
+                curr.push_str("${");
+                match read_braced(&mut chars) {
+                    Some(inner) => {
+                        curr.push_str(&inner);
+                        curr.push('}');
+                    }
+                    None => unterminated = true,
+                }
+                at_word_start = false;
+                continue;
+            }
+            '`' if !in_single_quote => {
+                curr.push('`');
+                unterminated = true;
+                while let Some(next) = chars.next() {
+                    curr.push(next);
+                    if next == '\\' {
+                        curr.extend(chars.next());
+                    } else if next == '`' {
+                        unterminated = false;
+                        break;
+                    }
+                }
+                at_word_start = false;
+                continue;
+            }
+            '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+            '"' if !in_single_quote => in_double_quote = !in_double_quote,
+            _ if in_single_quote || in_double_quote => {}
+            '#' if at_word_start => {
+                in_comment = true;
+                continue;
+            }
+            '{' if at_word_start && ends_word => brace_depth += 1,
+            '}' if at_word_start && ends_word && brace_depth > 0 => brace_depth -= 1,
+            ';' | '\n' if brace_depth == 0 => {
+                if !curr.trim().is_empty() {
+                    commands.push(curr.trim().to_string());
+                }
+                curr.clear();
+                at_word_start = true;
+                continue;
+            }
+            _ => {}
+        }
+
+        curr.push(c);
+        at_word_start = !in_single_quote
+            && !in_double_quote
+            && (c.is_whitespace() || matches!(c, ';' | '(' | ')'));
+    }
+
+    if !curr.trim().is_empty() {
+        commands.push(curr.trim().to_string());
+    }
+
+    let is_complete =
+        !in_single_quote && !in_double_quote && !to_escape && !unterminated && brace_depth == 0;
+    (commands, is_complete)
+}
+
+/// Splits a line into words without expanding them. Quotes, `$(...)`,
+/// backquotes and the `(...)` of an array assignment stay inside words.
+fn split_raw_words(line: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    let mut curr = String::new();
+
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if !in_single_quote => {
+                curr.push(c);
+                curr.extend(chars.next());
+                continue;
+            }
+            '\'' if !in_double_quote => in_single_quote = !in_single_quote,
+            '"' if !in_single_quote => in_double_quote = !in_double_quote,
+            _ if in_single_quote => {}
+            '$' if chars.peek() == Some(&'(') => {
+                chars.next();
+                curr.push_str("$(");
+                curr.extend(read_substitution(&mut chars));
+                curr.push(')');
+                continue;
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                curr.push_str("${");
+                curr.extend(read_braced(&mut chars));
+                curr.push('}');
+                continue;
+            }
+            '`' => {
                 curr.push(c);
+                while let Some(next) = chars.next() {
+                    curr.push(next);
+                    if next == '\\' {
+                        curr.extend(chars.next());
+                    } else if next == '`' {
+                        break;
+                    }
+                }
+                continue;
+            }
+            _ if in_double_quote => {}
+            '(' if curr.ends_with('=') => {
+                curr.push(c);
+                curr.extend(read_substitution(&mut chars));
+                curr.push(')');
+                continue;
+            }
+            '#' if curr.is_empty() => break,
+            _ if c.is_whitespace() => {
+                if !curr.is_empty() {
+                    words.push(std::mem::take(&mut curr));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        curr.push(c);
+    }
+
+    if !curr.is_empty() {
+        words.push(curr);
+    }
+
+    words
+}
+
+/// Splits an unexpanded `name=value`, `name+=value` or
+/// `name[subscript]=value` word into its name, subscript, whether it
+/// appends, and value.
+fn assignment_parts(word: &str) -> Option<(&str, Option<&str>, bool, &str)> {
+    let name_end = word.find(|c: char| c != '_' && !c.is_ascii_alphanumeric())?;
+    let name = &word[..name_end];
+    if !is_valid_name(name) {
+        return None;
+    }
+
+    let mut rest = &word[name_end..];
+    let mut subscript = None;
+    if let Some(inner) = rest.strip_prefix('[') {
+        let mut depth = 0;
+        let close = inner.find(|c: char| {
+            match c {
+                '[' => depth += 1,
+                ']' if depth == 0 => return true,
+                ']' => depth -= 1,
+                _ => {}
+            }
+            false
+        })?;
+        subscript = Some(&inner[..close]);
+        rest = &inner[close + 1..];
+    }
+
+    match rest.strip_prefix("+=") {
+        Some(value) => Some((name, subscript, true, value)),
+        None => Some((name, subscript, false, rest.strip_prefix('=')?)),
+    }
+}
+
+/// Expands an assignment word. The elements of a `(...)` list are split
+/// into fields like command arguments, except `[subscript]=value` ones.
+fn expand_assignment(word: &str, shell: &mut Shell) -> Option<Assignment> {
+    let (name, subscript, append, value) = assignment_parts(word)?;
+    let index = subscript.map(|subscript| expand_word(subscript, shell));
+    let value = match value.strip_prefix('(').and_then(|inner| inner.strip_suffix(')')) {
+        Some(inner) => {
+            let mut elements = Vec::new();
+            for element in split_raw_words(inner) {
+                let keyed = element
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once("]="));
+                match keyed {
+                    Some((key, value)) => {
+                        elements.push((Some(expand_word(key, shell)), expand_word(value, shell)));
+                    }
+                    None => {
+                        let fields = tokenize(&element, shell);
+                        elements.extend(fields.into_iter().map(|field| (None, field)));
+                    }
+                }
+            }
+            AssignedValue::Compound(elements)
+        }
+        None => AssignedValue::Scalar(expand_word(value, shell)),
+    };
+
+    Some(Assignment {
+        name: name.to_string(),
+        index,
+        append,
+        value,
+    })
+}
+
+/// Joins words for `set -x`, quoting those that need it.
+fn quote_words(words: &[String]) -> String {
+    let words: Vec<String> = words.iter().map(|word| quote_if_needed(word)).collect();
+    words.join(" ")
+}
+
+/// Shows an expanded assignment for `set -x`.
+fn trace_assignment(assignment: &Assignment) -> String {
+    let operator = if assignment.append { "+=" } else { "=" };
+    let value = match &assignment.value {
+        AssignedValue::Scalar(value) => quote_if_needed(value),
+        AssignedValue::Compound(elements) => {
+            let elements: Vec<String> = elements
+                .iter()
+                .map(|(key, value)| match key {
+                    Some(key) => format!("[{key}]={}", quote_if_needed(value)),
+                    None => quote_if_needed(value),
+                })
+                .collect();
+            format!("({})", elements.join(" "))
+        }
+    };
+
+    match &assignment.index {
+        Some(index) => format!("{}[{index}]{operator}{value}", assignment.name),
+        None => format!("{}{operator}{value}", assignment.name),
+    }
+}
+
+/// Parses the words after `declare` or `typeset`. Its assignments are
+/// expanded like assignment statements rather than as arguments.
+fn parse_declaration(words: &[String], shell: &mut Shell) -> Command {
+    let end = words
+        .iter()
+        .position(|word| is_redirect_operator(word))
+        .unwrap_or(words.len());
+    let redirection = match end < words.len() {
+        true => {
+            let tokens = tokenize(&words[end..].join(" "), shell);
+            parse_redirection(&tokens, shell.options.noclobber)
+        }
+        false => None,
+    };
+    if let Some(error) = redirection.as_ref().and_then(redirection_error) {
+        return error;
+    }
+
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut words = words[..end].iter().peekable();
+    while let Some(word) = words.next_if(|word| word.len() > 1 && word.starts_with(['-', '+'])) {
+        if word == "--" {
+            break;
+        }
+        options.push(word.clone());
+    }
+    for word in words {
+        match expand_assignment(word, shell) {
+            Some(assignment) => operands.push(Operand::Assignment(assignment)),
+            None => operands.extend(tokenize(word, shell).into_iter().map(Operand::Name)),
+        }
+    }
+    if shell.options.xtrace {
+        let words = operands.iter().map(|operand| match operand {
+            Operand::Name(name) => quote_if_needed(name),
+            Operand::Assignment(assignment) => trace_assignment(assignment),
+        });
+        let words: Vec<String> = std::iter::once("declare".to_string())
+            .chain(options.iter().cloned())
+            .chain(words)
+            .collect();
+        shell.trace(&words.join(" "));
+    }
+
+    Command::Declare {
+        options,
+        operands,
+        redirection,
+    }
+}
+
+/// Recognises `name() { body }` and `function name { body }`.
+fn parse_function_definition(line: &str) -> Option<(String, String)> {
+    let line = line.trim();
+    let (line, has_keyword) = match line.strip_prefix("function ") {
+        Some(rest) => (rest.trim_start(), true),
+        None => (line, false),
+    };
+
+    let name_end = line.find(|c: char| c == '(' || c.is_whitespace() || c == '{')?;
+    let name = &line[..name_end];
+    if name.is_empty() || name.contains(['$', '\'', '"', '=', '/']) {
+        return None;
+    }
+
+    let rest = line[name_end..].trim_start();
+    let rest = match rest.strip_prefix('(') {
+        Some(rest) => rest.trim_start().strip_prefix(')')?.trim_start(),
+        None if has_keyword => rest,
+        None => return None,
+    };
+
+    let body = rest.strip_prefix('{')?.strip_suffix('}')?;
+    if !body.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    Some((name.to_string(), body.trim().to_string()))
+}
+
+fn tokenize(input: &str, shell: &mut Shell) -> Vec<String> {
+    tokenize_words(input, shell)
+        .into_iter()
+        .map(|token| token.text)
+        .collect()
+}
+
+/// Splits `input` into expanded words, like `tokenize`, keeping how much
+/// of each was written out unquoted.
+fn tokenize_words(input: &str, shell: &mut Shell) -> Vec<Token> {
+    let input = input.trim();
+    let pathnames = !shell.options.noglob;
+
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut curr = Word::default();
+
+    let mut in_single_quote = false;
+    let mut in_double_quote = false;
+    let mut to_escape = false;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        if to_escape {
+            if in_double_quote && (c == '"' || c == '\\' || c == '`' || c == '$') {
+                curr.push(c, true);
                 to_escape = false;
             } else if in_double_quote {
-                curr.push('\\');
-                curr.push(c);
+                curr.push('\\', true);
+                curr.push(c, true);
                 to_escape = false;
-            } else if !in_double_quote && !in_double_quote {
-                curr.push(c);
+            } else {
+                curr.push(c, true);
                 to_escape = false;
             }
 
@@ -38,44 +655,175 @@ fn tokenize(input: &str) -> Vec<String> {
         }
 
         if c == '\'' && !in_double_quote {
+            curr.end_unquoted();
+            curr.quoted |= !in_single_quote;
             in_single_quote = !in_single_quote;
             continue;
         }
 
         if c == '"' && !in_single_quote {
+            curr.end_unquoted();
+            curr.quoted |= !in_double_quote;
             in_double_quote = !in_double_quote;
             continue;
         }
 
-        if c == '\\' && ((!in_single_quote && !in_double_quote) || in_double_quote) {
+        if c == '\\' && !in_single_quote {
             to_escape = true;
             continue;
         }
 
+        if c == '#' && curr.text.is_empty() && !curr.quoted && !in_single_quote && !in_double_quote {
+            break;
+        }
+
+        if (c == '$' || c == '`') && !in_single_quote {
+            curr.end_unquoted();
+            let expansion = match c {
+                '$' => expand_dollar(&mut chars, shell),
+                _ => expand_backquote(&mut chars, shell),
+            };
+            // Like quotes, assignments keep expansions in their values whole.
+            let in_assignment = parse_assignment(&curr.text).is_some()
+                && tokens.iter().all(|token| parse_assignment(&token.text).is_some());
+            match expansion {
+                None => curr.push(c, in_double_quote),
+                Some(Expansion::Text(value)) if in_double_quote || in_assignment => {
+                    curr.push_str(&value, true)
+                }
+                Some(Expansion::Text(value)) => {
+                    push_fields(&value, &mut curr, &mut tokens, pathnames);
+                }
+                Some(Expansion::Fields(fields)) if in_double_quote => {
+                    if fields.is_empty() && curr.text.is_empty() {
+                        curr.quoted = false;
+                    }
+                    for (index, field) in fields.iter().enumerate() {
+                        if index > 0 {
+                            curr.finish(&mut tokens, pathnames);
+                            curr.quoted = true;
+                        }
+                        curr.push_str(field, true);
+                    }
+                }
+                Some(Expansion::Fields(fields)) => {
+                    push_fields(&fields.join(" "), &mut curr, &mut tokens, pathnames);
+                }
+            }
+            continue;
+        }
+
         if c.is_whitespace() && !in_single_quote && !in_double_quote {
-            if !curr.is_empty() {
-                tokens.push(curr.clone());
-                curr.clear();
+            if curr.is_word() {
+                curr.finish(&mut tokens, pathnames);
             }
         } else {
-            curr.push(c);
+            curr.push(c, in_single_quote || in_double_quote);
         }
     }
 
-    if !curr.is_empty() {
-        tokens.push(curr.clone());
+    if curr.is_word() {
+        curr.finish(&mut tokens, pathnames);
     }
 
     tokens
 }
 
+/// An expanded word of a command. Only its first `unquoted` bytes were
+/// written out without quotes, escapes or expansions, so only they may
+/// make up an operator, as in `2>"$file"` but not `'>'`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub text: String,
+    pub unquoted: usize,
+}
+
+/// A word being read by `tokenize`, along with the pattern it stands for
+/// in pathname expansion, in which quoted characters are escaped.
+#[derive(Default)]
+struct Word {
+    text: String,
+    pattern: String,
+    /// Set once quotes are seen, so that `''` still produces an empty word.
+    quoted: bool,
+    /// The length of the text written out unquoted at the start.
+    unquoted: usize,
+    unquoted_ended: bool,
+}
+
+impl Word {
+    fn push(&mut self, c: char, quoted: bool) {
+        if quoted {
+            self.end_unquoted();
+        } else if !self.unquoted_ended {
+            self.unquoted += c.len_utf8();
+        }
+        self.text.push(c);
+        match quoted {
+            true => self.pattern.push_str(&glob::escape(&c.to_string())),
+            false => self.pattern.push(c),
+        }
+    }
+
+    fn push_str(&mut self, text: &str, quoted: bool) {
+        for c in text.chars() {
+            self.push(c, quoted);
+        }
+    }
+
+    fn end_unquoted(&mut self) {
+        self.unquoted_ended = true;
+    }
+
+    fn is_word(&self) -> bool {
+        !self.text.is_empty() || self.quoted
+    }
+
+    /// Adds the word to `tokens`, replaced by the paths it matches if it
+    /// is a pattern that matches any, and starts a new one.
+    fn finish(&mut self, tokens: &mut Vec<Token>, pathnames: bool) {
+        let word = std::mem::take(self);
+        let paths = match pathnames && glob::is_pattern(&word.pattern) {
+            true => glob::expand_paths(&word.pattern),
+            false => Vec::new(),
+        };
+        match paths.is_empty() {
+            true => tokens.push(Token {
+                text: word.text,
+                unquoted: word.unquoted,
+            }),
+            false => tokens.extend(paths.into_iter().map(|text| Token { text, unquoted: 0 })),
+        }
+    }
+}
+
+/// Word-splits the result of an unquoted expansion onto the current word.
+fn push_fields(value: &str, curr: &mut Word, tokens: &mut Vec<Token>, pathnames: bool) {
+    if value.starts_with(char::is_whitespace) && curr.is_word() {
+        curr.finish(tokens, pathnames);
+    }
+
+    let fields: Vec<&str> = value.split_whitespace().collect();
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            curr.finish(tokens, pathnames);
+        }
+        curr.end_unquoted();
+        curr.push_str(field, false);
+    }
+
+    if value.ends_with(char::is_whitespace) && !curr.text.is_empty() {
+        curr.finish(tokens, pathnames);
+    }
+}
+
 fn split_tokens(tokens: Vec<String>) -> (Vec<String>, Vec<String>) {
     let mut found_redirect_operator = false;
 
     tokens.into_iter().partition(|token| {
         if found_redirect_operator {
             false
-        } else if REDIRECT_OPERATORS.contains(&token.as_str()) {
+        } else if is_redirect_operator(token) {
             found_redirect_operator = true;
             false
         } else {
@@ -84,18 +832,59 @@ fn split_tokens(tokens: Vec<String>) -> (Vec<String>, Vec<String>) {
     })
 }
 
-fn parse_redirection(redirection_tokens: &Vec<String>) -> Option<Redirection> {
-    if redirection_tokens.is_empty() {
+/// Whether `token` starts a redirection: one of `REDIRECT_OPERATORS`, or a
+/// duplication such as `2>&1`, which carries its target with it.
+fn is_redirect_operator(token: &str) -> bool {
+    REDIRECT_OPERATORS.contains(&token) || parse_duplication(token).is_some()
+}
+
+/// Splits `>&N`, `1>&N` or `2>&N` into the channel and N.
+fn parse_duplication(token: &str) -> Option<(OutputChannel, &str)> {
+    let (channel, fd) = match token.split_once(">&")? {
+        ("" | "1", fd) => (OutputChannel::Stdout, fd),
+        ("2", fd) => (OutputChannel::Stderr, fd),
+        _ => return None,
+    };
+
+    (!fd.is_empty() && fd.chars().all(|c| c.is_ascii_digit())).then_some((channel, fd))
+}
+
+/// Parses a redirection. Under `set -o noclobber`, `>` won't overwrite
+/// existing files, but `>|` still does.
+fn parse_redirection(redirection_tokens: &[String], noclobber: bool) -> Option<Redirection> {
+    let operator = redirection_tokens.first()?;
+    if let Some((channel, fd)) = parse_duplication(operator) {
+        return Some(Redirection {
+            kind: RedirectionKind::Duplicate,
+            channel,
+            file: fd.to_string(),
+        });
+    }
+    if redirection_tokens.len() < 2 {
         return None;
     }
 
+    let truncate = match noclobber {
+        true => RedirectionKind::NoClobber,
+        false => RedirectionKind::Redirect,
+    };
     match redirection_tokens[0].as_str() {
         ">" | "1>" => Some(Redirection {
-            kind: RedirectionKind::Redirect,
+            kind: truncate,
             channel: OutputChannel::Stdout,
             file: redirection_tokens[1].clone(),
         }),
         "2>" => Some(Redirection {
+            kind: truncate,
+            channel: OutputChannel::Stderr,
+            file: redirection_tokens[1].clone(),
+        }),
+        ">|" | "1>|" => Some(Redirection {
+            kind: RedirectionKind::Redirect,
+            channel: OutputChannel::Stdout,
+            file: redirection_tokens[1].clone(),
+        }),
+        "2>|" => Some(Redirection {
             kind: RedirectionKind::Redirect,
             channel: OutputChannel::Stderr,
             file: redirection_tokens[1].clone(),
@@ -114,26 +903,55 @@ fn parse_redirection(redirection_tokens: &Vec<String>) -> Option<Redirection> {
     }
 }
 
-fn parse(command_tokens: &Vec<String>, redirection: Option<Redirection>) -> Command {
+/// Checks a redirection before its command runs, returning a command that
+/// reports the error if the file may not be written.
+fn redirection_error(redirection: &Redirection) -> Option<Command> {
+    let error = check_redirection(redirection).err()?;
+    Some(Command::RedirectionError {
+        message: format!("{}: {}", redirection.file, error_message(&error)),
+    })
+}
+
+fn parse(command_tokens: &[String], redirection: Option<Redirection>) -> Command {
+    let assignments: Option<Vec<Assignment>> = command_tokens
+        .iter()
+        .map(|token| parse_assignment(token).map(|(name, value)| Assignment::scalar(name, value)))
+        .collect();
+    if let Some(assignments) = assignments {
+        return Command::Assign { assignments };
+    }
+
     match command_tokens[0].as_str() {
         "echo" => Command::Echo {
             args: command_tokens[1..].to_vec(),
             redirection,
         },
         "exit" => Command::Exit {
-            _arg: if command_tokens.len() > 1 {
-                command_tokens[1].parse().unwrap()
-            } else {
-                0
-            },
+            arg: command_tokens.get(1).cloned(),
         },
         "type" => Command::Type {
             arg: command_tokens[1].parse().unwrap(),
             redirection,
         },
-        "pwd" => Command::Pwd { redirection },
+        "pwd" => Command::Pwd {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
         "cd" => Command::Cd {
-            arg: expand_home_path(&command_tokens[1]),
+            args: command_tokens[1..].iter().map(|path| expand_home_path(path)).collect(),
+            redirection,
+        },
+        "pushd" => Command::Pushd {
+            args: command_tokens[1..].iter().map(|path| expand_home_path(path)).collect(),
+            redirection,
+        },
+        "popd" => Command::Popd {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "dirs" => Command::Dirs {
+            args: command_tokens[1..].to_vec(),
+            redirection,
         },
         "cat" => {
             let destinations: Vec<String> = command_tokens[1..]
@@ -146,6 +964,54 @@ fn parse(command_tokens: &Vec<String>, redirection: Option<Redirection>) -> Comm
                 redirection,
             }
         }
+        "trap" => Command::Trap {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "set" => Command::Set {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "shift" => Command::Shift {
+            arg: command_tokens.get(1).cloned(),
+        },
+        "source" | "." => Command::Source {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "return" => Command::Return {
+            arg: command_tokens.get(1).cloned(),
+        },
+        "history" => Command::History {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "alias" => Command::Alias {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "unalias" => Command::Unalias {
+            args: command_tokens[1..].to_vec(),
+        },
+        "complete" => Command::Complete {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "printf" => Command::Printf {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
+        "read" => Command::Read {
+            args: command_tokens[1..].to_vec(),
+        },
+        "test" | "[" => Command::Test {
+            name: command_tokens[0].clone(),
+            args: command_tokens[1..].to_vec(),
+        },
+        "compgen" => Command::Compgen {
+            args: command_tokens[1..].to_vec(),
+            redirection,
+        },
         _ => Command::External {
             name: command_tokens[0].to_string(),
             args: command_tokens[1..].to_vec(),
@@ -163,7 +1029,7 @@ mod tests {
         let input = "echo hello world";
         let expected = vec!["echo".to_string(), "hello".to_string(), "world".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -172,7 +1038,7 @@ mod tests {
         let input = "echo hello    world";
         let expected = vec!["echo".to_string(), "hello".to_string(), "world".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -181,7 +1047,7 @@ mod tests {
         let input = "type echo";
         let expected = vec!["type".to_string(), "echo".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -190,7 +1056,7 @@ mod tests {
         let input = "ls";
         let expected = vec!["ls".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -199,7 +1065,7 @@ mod tests {
         let input = "cd ~/Documents";
         let expected = vec!["cd".to_string(), "~/Documents".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -208,7 +1074,7 @@ mod tests {
         let input = "echo 'world     test'";
         let expected = vec!["echo".to_string(), "world     test".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -222,7 +1088,7 @@ mod tests {
             "foo".to_string(),
         ];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -231,7 +1097,7 @@ mod tests {
         let input = r"echo hello\ \ \ \ \ \ world";
         let expected = vec!["echo".to_string(), "hello      world".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -243,7 +1109,7 @@ mod tests {
             "hello\"insidequotesscript\"".to_string(),
         ];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -252,7 +1118,7 @@ mod tests {
         let input = r#"echo "hello'script'\\n'world""#;
         let expected = vec!["echo".to_string(), r"hello'script'\n'world".to_string()];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -266,7 +1132,164 @@ mod tests {
             "/tmp/foo/baz.md".to_string(),
         ];
 
-        let result = tokenize(input);
+        let result = tokenize(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_empty_quoted_word() {
+        let input = "trap '' INT";
+        let expected = vec!["trap".to_string(), "".to_string(), "INT".to_string()];
+
+        let result = tokenize(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_positional_parameters() {
+        let mut shell = Shell::new();
+        shell.positional = vec!["one".to_string(), "two words".to_string()];
+        let input = r#"echo $1 "$2" $2 $#"#;
+        let expected = vec![
+            "echo".to_string(),
+            "one".to_string(),
+            "two words".to_string(),
+            "two".to_string(),
+            "words".to_string(),
+            "2".to_string(),
+        ];
+
+        let result = tokenize(input, &mut shell);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_command_substitution() {
+        let mut shell = Shell::new();
+        let input = "x=$(/bin/echo a  b) echo \"$(/bin/echo 'c)'; /bin/echo d)\" `/bin/echo e f`";
+        let expected = vec![
+            "x=a b".to_string(),
+            "echo".to_string(),
+            "c)\nd".to_string(),
+            "e".to_string(),
+            "f".to_string(),
+        ];
+
+        let result = tokenize(input, &mut shell);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_quoted_at_keeps_parameters_separate() {
+        let mut shell = Shell::new();
+        shell.positional = vec!["a b".to_string(), "c".to_string()];
+        let input = r#"echo "x$@y" '$@'"#;
+        let expected = vec![
+            "echo".to_string(),
+            "xa b".to_string(),
+            "cy".to_string(),
+            "$@".to_string(),
+        ];
+
+        let result = tokenize(input, &mut shell);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_tokenize_stops_at_comment() {
+        let input = "echo hi # a comment";
+        let expected = vec!["echo".to_string(), "hi".to_string()];
+
+        let result = tokenize(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_split_list_on_pipes_and_connectors() {
+        let input = "! a 'b|c' | d && [[ x || y ]] || f() { g | h; }";
+        let expected = vec![
+            (
+                None,
+                Pipeline {
+                    negated: true,
+                    commands: vec!["a 'b|c'".to_string(), "d".to_string()],
+                },
+            ),
+            (
+                Some(Connector::And),
+                Pipeline {
+                    negated: false,
+                    commands: vec!["[[ x || y ]]".to_string()],
+                },
+            ),
+            (
+                Some(Connector::Or),
+                Pipeline {
+                    negated: false,
+                    commands: vec!["f() { g | h; }".to_string()],
+                },
+            ),
+        ];
+
+        let result = split_list(input);
+        assert_eq!(result, Ok(expected));
+        assert!(split_list("a | | b").is_err());
+        assert!(split_list("a &&").is_err());
+        assert_eq!(split_list("echo ${x:-a|b}").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_split_commands_on_semicolons_and_newlines() {
+        let input = "echo a; echo 'b;c'\n# note\necho d \\\ne";
+        let expected = vec![
+            "echo a".to_string(),
+            "echo 'b;c'".to_string(),
+            "echo d e".to_string(),
+        ];
+
+        let result = split_commands(input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_split_commands_keeps_function_body() {
+        let input = "greet() {\n  echo hi; echo there\n}\ngreet";
+        let expected = vec![
+            "greet() {\n  echo hi; echo there\n}".to_string(),
+            "greet".to_string(),
+        ];
+
+        let result = split_commands(input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_is_complete_with_open_brace_or_quote() {
+        assert!(!is_complete("greet() {\n  echo hi"));
+        assert!(!is_complete("echo 'unterminated"));
+        assert!(!is_complete("echo trailing \\"));
+        assert!(!is_complete("echo $(date;"));
+        assert!(!is_complete("echo `date"));
+        assert!(!is_complete("echo ${x:-"));
+        assert!(is_complete("echo ${x:-a;b}; echo ${y-}"));
+        assert!(is_complete("greet() { echo hi; }"));
+    }
+
+    #[test]
+    fn test_parse_function_definition() {
+        let input = "greet() { echo hi; echo there; }";
+        let expected = Some(("greet".to_string(), "echo hi; echo there;".to_string()));
+
+        let result = parse_function_definition(input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_function_definition_with_keyword() {
+        let input = "function greet {\n  echo hi\n}";
+        let expected = Some(("greet".to_string(), "echo hi".to_string()));
+
+        let result = parse_function_definition(input);
         assert_eq!(result, expected);
     }
 
@@ -296,7 +1319,54 @@ mod tests {
             file: String::from("/tmp/foo/baz.md"),
         });
 
-        let result = parse_redirection(&input);
+        let result = parse_redirection(&input, false);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_redirection_noclobber() {
+        let input = vec![">".to_string(), "out.txt".to_string()];
+        let result = parse_redirection(&input, true).map(|redirection| redirection.kind);
+        assert_eq!(result, Some(RedirectionKind::NoClobber));
+
+        let input = vec!["2>|".to_string(), "out.txt".to_string()];
+        let expected = Some(Redirection {
+            kind: RedirectionKind::Redirect,
+            channel: OutputChannel::Stderr,
+            file: String::from("out.txt"),
+        });
+        assert_eq!(parse_redirection(&input, true), expected);
+    }
+
+    #[test]
+    fn test_parse_redirection_duplication() {
+        let input = vec!["2>&1".to_string()];
+        let expected = Some(Redirection {
+            kind: RedirectionKind::Duplicate,
+            channel: OutputChannel::Stderr,
+            file: String::from("1"),
+        });
+        assert_eq!(parse_redirection(&input, false), expected);
+
+        let input = vec![">&4".to_string()];
+        let result = parse_redirection(&input, true).map(|redirection| redirection.file);
+        assert_eq!(result, Some("4".to_string()));
+        assert!(!is_redirect_operator("3>&1"));
+        assert!(!is_redirect_operator(">&x"));
+    }
+
+    #[test]
+    fn test_parse_command_exec_keeps_quoting() {
+        let input = r#"exec echo '>' 2>"out file" 3>$SHELL_TEST_UNSET"#;
+        let token = |text: &str, unquoted: usize| Token {
+            text: text.to_string(),
+            unquoted,
+        };
+        let expected = Command::Exec {
+            args: vec![token("echo", 4), token(">", 0), token("2>out file", 2), token("3>", 2)],
+        };
+
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -330,16 +1400,78 @@ mod tests {
             redirection: None,
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_command_bracket_test() {
+        let input = "[ -f \"my file\" ]";
+        let expected = Command::Test {
+            name: "[".to_string(),
+            args: vec!["-f".to_string(), "my file".to_string(), "]".to_string()],
+        };
+
+        let result = parse_command(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_command_conditional_keeps_words_whole() {
+        let mut shell = Shell::new();
+        shell.variables.set("NAME", "a b");
+        let input = "[[ $NAME == \"a\"* ]]";
+        let expected = Command::Conditional {
+            words: vec![
+                ConditionalWord {
+                    text: "a b".to_string(),
+                    pattern: "a b".to_string(),
+                    regex: "a b".to_string(),
+                    bare: false,
+                },
+                ConditionalWord::literal("=="),
+                ConditionalWord {
+                    text: "a*".to_string(),
+                    pattern: "a*".to_string(),
+                    regex: "a*".to_string(),
+                    bare: false,
+                },
+                ConditionalWord::literal("]]"),
+            ],
+        };
+
+        let result = parse_command(input, &mut shell);
         assert_eq!(result, expected);
     }
 
     #[test]
     fn test_parse_command_exit() {
         let input = "exit 0";
-        let expected = Command::Exit { _arg: 0 };
+        let expected = Command::Exit {
+            arg: Some("0".to_string()),
+        };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_command_exit_without_status() {
+        let input = "exit";
+        let expected = Command::Exit { arg: None };
+
+        let result = parse_command(input, &mut Shell::new());
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_command_assignment() {
+        let input = "FOO=bar BAZ=";
+        let expected = Command::Assign {
+            assignments: vec![Assignment::scalar("FOO", "bar"), Assignment::scalar("BAZ", "")],
+        };
+
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -351,7 +1483,7 @@ mod tests {
             redirection: None,
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -364,7 +1496,7 @@ mod tests {
             redirection: None,
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -372,10 +1504,11 @@ mod tests {
     fn test_parse_command_cd() {
         let input = "cd ~/Documents";
         let expected = Command::Cd {
-            arg: "/home/cdg/Documents".to_string(),
+            args: vec![expand_home_path("~/Documents")],
+            redirection: None,
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -391,7 +1524,7 @@ mod tests {
             redirection: None,
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 
@@ -407,7 +1540,7 @@ mod tests {
             }),
         };
 
-        let result = parse_command(input);
+        let result = parse_command(input, &mut Shell::new());
         assert_eq!(result, expected);
     }
 }
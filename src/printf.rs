@@ -0,0 +1,532 @@
+use crate::variables::{is_valid_name, Assignment, Variables};
+use std::ffi::{CStr, CString};
+use std::iter::Peekable;
+use std::str::Chars;
+
+const USAGE: &str = "printf: usage: printf [-v var] format [arguments]\n";
+
+/// Implements `printf [-v var] format [arguments]`, returning what it
+/// printed and any error messages. The format is reused while arguments
+/// remain, and missing arguments read as empty strings or zero. With `-v`
+/// the output is assigned to the variable instead.
+pub fn printf(args: &[String], variables: &mut Variables) -> (String, String) {
+    let mut args = args;
+    let mut target = None;
+    if args.first().is_some_and(|arg| arg == "-v") {
+        match args.get(1) {
+            Some(name) if is_valid_name(name) => target = Some(name),
+            Some(name) => {
+                return (
+                    String::new(),
+                    format!("printf: `{name}': not a valid identifier\n"),
+                )
+            }
+            None => return (String::new(), USAGE.to_string()),
+        }
+        args = &args[2..];
+    }
+    if args.first().is_some_and(|arg| arg == "--") {
+        args = &args[1..];
+    }
+    let Some((format, arguments)) = args.split_first() else {
+        return (String::new(), USAGE.to_string());
+    };
+
+    let mut formatter = Formatter {
+        arguments,
+        next: 0,
+        output: String::new(),
+        errors: String::new(),
+    };
+    loop {
+        let start = formatter.next;
+        let stopped = formatter.format(format);
+        if stopped || formatter.next == start || formatter.next >= arguments.len() {
+            break;
+        }
+    }
+
+    match target {
+        Some(name) => {
+            let mut errors = formatter.errors;
+            if let Err(message) = variables.assign(&Assignment::scalar(name, &formatter.output)) {
+                errors.push_str(&format!("printf: {message}\n"));
+            }
+            (String::new(), errors)
+        }
+        None => (formatter.output, formatter.errors),
+    }
+}
+
+/// Decodes the backslash escapes of a `%b` argument or of `echo -e`,
+/// where octal escapes are written `\0NNN`. The flag is set if `\c` cut
+/// the output short.
+pub fn expand_escapes(text: &str) -> (String, bool) {
+    let mut expanded = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            expanded.push(c);
+            continue;
+        }
+        match read_escape(&mut chars, true) {
+            Escape::Text(text) => expanded.push_str(&text),
+            Escape::Stop => return (expanded, true),
+        }
+    }
+
+    (expanded, false)
+}
+
+enum Escape {
+    Text(String),
+    /// `\c`, which ends the output.
+    Stop,
+}
+
+/// Decodes the escape whose backslash has just been read. Escapes in `%b`
+/// arguments and `echo -e` (`echo_style`) spell octal as `\0NNN` and may
+/// use `\c`; in a format, `\NNN` is octal and quotes may be escaped.
+fn read_escape(chars: &mut Peekable<Chars>, echo_style: bool) -> Escape {
+    let Some(c) = chars.next() else {
+        return Escape::Text("\\".to_string());
+    };
+
+    let decoded = match c {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'e' | 'E' => Some('\x1b'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        '\\' => Some('\\'),
+        '"' | '\'' if !echo_style => Some(c),
+        'c' if echo_style => return Escape::Stop,
+        '0' if echo_style => char::from_u32(read_digits(chars, 8, 3).unwrap_or(0)),
+        '0'..='7' if !echo_style => {
+            let mut code = c.to_digit(8).unwrap();
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(digit) => {
+                        code = code * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            char::from_u32(code)
+        }
+        'x' => read_digits(chars, 16, 2).and_then(char::from_u32),
+        'u' => read_digits(chars, 16, 4).and_then(char::from_u32),
+        'U' => read_digits(chars, 16, 8).and_then(char::from_u32),
+        _ => None,
+    };
+
+    match decoded {
+        Some(decoded) => Escape::Text(decoded.to_string()),
+        None => Escape::Text(format!("\\{c}")),
+    }
+}
+
+/// Reads up to `max` digits in `radix`, or `None` if there are none.
+fn read_digits(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> Option<u32> {
+    let mut value = None;
+    for _ in 0..max {
+        let Some(digit) = chars.peek().and_then(|c| c.to_digit(radix)) else {
+            break;
+        };
+        value = Some(value.unwrap_or(0) * radix + digit);
+        chars.next();
+    }
+
+    value
+}
+
+enum Number {
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+struct Formatter<'a> {
+    arguments: &'a [String],
+    next: usize,
+    output: String,
+    errors: String,
+}
+
+impl Formatter<'_> {
+    /// Formats `format` once, returning whether output should stop because
+    /// of `\c` or an invalid conversion.
+    fn format(&mut self, format: &str) -> bool {
+        let mut chars = format.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match read_escape(&mut chars, false) {
+                    Escape::Text(text) => self.output.push_str(&text),
+                    Escape::Stop => return true,
+                },
+                '%' if chars.peek() == Some(&'%') => {
+                    chars.next();
+                    self.output.push('%');
+                }
+                '%' => {
+                    if self.conversion(&mut chars) {
+                        return true;
+                    }
+                }
+                _ => self.output.push(c),
+            }
+        }
+
+        false
+    }
+
+    /// Formats one conversion, whose `%` has just been read.
+    fn conversion(&mut self, chars: &mut Peekable<Chars>) -> bool {
+        let mut flags = String::new();
+        while let Some(&c) = chars
+            .peek()
+            .filter(|c| matches!(c, '-' | '+' | ' ' | '#' | '0'))
+        {
+            flags.push(c);
+            chars.next();
+        }
+
+        let mut width = match chars.peek() {
+            Some('*') => {
+                chars.next();
+                Some(self.next_integer())
+            }
+            _ => read_digits(chars, 10, 9).map(i64::from),
+        };
+        if let Some(negative) = width.filter(|width| *width < 0) {
+            flags.push('-');
+            width = Some(-negative);
+        }
+
+        let precision = match chars.peek() {
+            Some('.') => {
+                chars.next();
+                match chars.peek() {
+                    Some('*') => {
+                        chars.next();
+                        Some(self.next_integer()).filter(|precision| *precision >= 0)
+                    }
+                    _ => Some(read_digits(chars, 10, 9).map_or(0, i64::from)),
+                }
+            }
+            _ => None,
+        };
+
+        let Some(conversion) = chars.next() else {
+            self.errors
+                .push_str("printf: `%': missing format character\n");
+            return true;
+        };
+
+        let text = match conversion {
+            's' => self.next_argument().to_string(),
+            'b' => {
+                let (text, stopped) = expand_escapes(self.next_argument());
+                if stopped {
+                    self.output.push_str(&pad(&text, &flags, width));
+                    return true;
+                }
+                text
+            }
+            'q' => quote(self.next_argument()),
+            'c' => self.next_argument().chars().take(1).collect(),
+            'd' | 'i' => {
+                let number = Number::Signed(self.next_integer());
+                self.output
+                    .push_str(&format_number(&flags, width, precision, "lld", number));
+                return false;
+            }
+            'u' | 'o' | 'x' | 'X' => {
+                let number = Number::Unsigned(self.next_integer() as u64);
+                let length = format!("ll{conversion}");
+                self.output
+                    .push_str(&format_number(&flags, width, precision, &length, number));
+                return false;
+            }
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => {
+                let number = Number::Float(self.next_float());
+                let conversion = conversion.to_string();
+                self.output.push_str(&format_number(
+                    &flags,
+                    width,
+                    precision,
+                    &conversion,
+                    number,
+                ));
+                return false;
+            }
+            _ => {
+                self.errors.push_str(&format!(
+                    "printf: `{conversion}': invalid format character\n"
+                ));
+                return true;
+            }
+        };
+
+        let text = match precision {
+            Some(precision) if conversion != 'c' => text.chars().take(precision as usize).collect(),
+            _ => text,
+        };
+        self.output.push_str(&pad(&text, &flags, width));
+
+        false
+    }
+
+    fn next_argument(&mut self) -> &str {
+        let argument = self.arguments.get(self.next).map_or("", String::as_str);
+        self.next += 1;
+
+        argument
+    }
+
+    /// Reads an integer argument, in decimal, octal or hex as C does. A
+    /// leading quote gives the code of the character after it.
+    fn next_integer(&mut self) -> i64 {
+        let argument = self.next_argument().to_string();
+        if let Some(code) = character_code(&argument) {
+            return code;
+        }
+
+        let (value, valid) = parse_with(&argument, |text, end| unsafe {
+            libc::strtoll(text, end, 0)
+        });
+        if !valid {
+            self.errors
+                .push_str(&format!("printf: {argument}: invalid number\n"));
+        }
+
+        value
+    }
+
+    fn next_float(&mut self) -> f64 {
+        let argument = self.next_argument().to_string();
+        if let Some(code) = character_code(&argument) {
+            return code as f64;
+        }
+
+        let (value, valid) = parse_with(&argument, |text, end| unsafe { libc::strtod(text, end) });
+        if !valid {
+            self.errors
+                .push_str(&format!("printf: {argument}: invalid number\n"));
+        }
+
+        value
+    }
+}
+
+fn character_code(argument: &str) -> Option<i64> {
+    let rest = argument.strip_prefix(['\'', '"'])?;
+    Some(rest.chars().next().map_or(0, |c| c as i64))
+}
+
+/// Parses `text` with a C conversion function, returning the value and
+/// whether all of the text was used. Empty text is zero.
+fn parse_with<T: Default>(
+    text: &str,
+    parse: impl Fn(*const libc::c_char, *mut *mut libc::c_char) -> T,
+) -> (T, bool) {
+    if text.is_empty() {
+        return (T::default(), true);
+    }
+    let Ok(c_text) = CString::new(text) else {
+        return (T::default(), false);
+    };
+
+    let mut end = std::ptr::null_mut();
+    let value = parse(c_text.as_ptr(), &mut end);
+    let rest = unsafe { CStr::from_ptr(end) };
+
+    (
+        value,
+        rest.is_empty() && !std::ptr::eq(end, c_text.as_ptr()),
+    )
+}
+
+/// Formats a number with C's `snprintf`, so that flags, width and precision
+/// behave exactly as they do in C.
+fn format_number(
+    flags: &str,
+    width: Option<i64>,
+    precision: Option<i64>,
+    conversion: &str,
+    number: Number,
+) -> String {
+    let mut spec = format!("%{flags}");
+    if let Some(width) = width {
+        spec.push_str(&width.to_string());
+    }
+    if let Some(precision) = precision {
+        spec.push_str(&format!(".{precision}"));
+    }
+    spec.push_str(conversion);
+    let spec = CString::new(spec).unwrap();
+
+    let mut buffer = vec![0u8; 64];
+    loop {
+        let pointer = buffer.as_mut_ptr() as *mut libc::c_char;
+        let length = unsafe {
+            match number {
+                Number::Signed(value) => libc::snprintf(
+                    pointer,
+                    buffer.len(),
+                    spec.as_ptr(),
+                    value as libc::c_longlong,
+                ),
+                Number::Unsigned(value) => libc::snprintf(
+                    pointer,
+                    buffer.len(),
+                    spec.as_ptr(),
+                    value as libc::c_ulonglong,
+                ),
+                Number::Float(value) => libc::snprintf(pointer, buffer.len(), spec.as_ptr(), value),
+            }
+        };
+        if length < 0 {
+            return String::new();
+        }
+        if (length as usize) < buffer.len() {
+            buffer.truncate(length as usize);
+            return String::from_utf8_lossy(&buffer).into_owned();
+        }
+        buffer.resize(length as usize + 1, 0);
+    }
+}
+
+/// Pads `text` to `width` characters, on the left unless `-` is given.
+fn pad(text: &str, flags: &str, width: Option<i64>) -> String {
+    let width = width.unwrap_or(0) as usize;
+    match flags.contains('-') {
+        true => format!("{text:<width$}"),
+        false => format!("{text:>width$}"),
+    }
+}
+
+/// Quotes `text` for `%q` so that the shell reads it back unchanged, using
+/// `$'...'` when it has control characters.
+fn quote(text: &str) -> String {
+    if text.is_empty() {
+        return "''".to_string();
+    }
+
+    if text.chars().any(char::is_control) {
+        let mut quoted = String::from("$'");
+        for c in text.chars() {
+            match c {
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                '\r' => quoted.push_str("\\r"),
+                '\x1b' => quoted.push_str("\\E"),
+                '\'' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                _ if c.is_control() => quoted.push_str(&format!("\\{:03o}", c as u32)),
+                _ => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        return quoted;
+    }
+
+    let mut quoted = String::new();
+    for c in text.chars() {
+        if !c.is_alphanumeric()
+            && !matches!(c, '_' | '-' | '.' | '/' | ',' | ':' | '+' | '@' | '%' | '=')
+        {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> (String, String) {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        printf(&args, &mut Variables::default())
+    }
+
+    #[test]
+    fn test_printf_reuses_format_for_remaining_arguments() {
+        let input = ["%s=%d\\n", "a", "1", "b", "2", "c"];
+        let expected = "a=1\nb=2\nc=0\n".to_string();
+
+        let (result, _) = run(&input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_printf_widths_precision_and_flags() {
+        let input = [
+            "[%5s|%-5s|%.2s|%05d|%+d|%x|%#o|%*d|%.3f|%e]",
+            "ab",
+            "ab",
+            "abc",
+            "42",
+            "7",
+            "255",
+            "8",
+            "4",
+            "3",
+            "3.14159",
+            "1500",
+        ];
+        let expected = "[   ab|ab   |ab|00042|+7|ff|010|   3|3.142|1.500000e+03]".to_string();
+
+        let (result, _) = run(&input);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_printf_escapes_and_special_conversions() {
+        let (result, _) = run(&[
+            "%b|%q|%c|%d|%%|\\101",
+            "a\\tb\\0101",
+            "it's here",
+            "xyz",
+            "'A",
+        ]);
+        assert_eq!(result, "a\tbA|it\\'s\\ here|x|65|%|A");
+
+        let (result, _) = run(&["%s %b %s\\n", "one", "two\\cignored", "three"]);
+        assert_eq!(result, "one two");
+    }
+
+    #[test]
+    fn test_printf_reports_invalid_numbers() {
+        let result = run(&["%d,%d", "12abc", "0x1f"]);
+        assert_eq!(
+            result,
+            (
+                "12,31".to_string(),
+                "printf: 12abc: invalid number\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_printf_assigns_with_v() {
+        let mut variables = Variables::default();
+        let args: Vec<String> = ["-v", "out", "%03d", "7"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        let result = printf(&args, &mut variables);
+        assert_eq!(result, (String::new(), String::new()));
+        assert_eq!(variables.get("out"), Some("007"));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::dirs;
+use crate::expansion;
+use crate::shell::Shell;
+use crate::utils::abbreviate_home;
+use std::env;
+use std::ffi::CStr;
+use std::mem;
+
+const DEFAULT_PS1: &str = "$ ";
+const DEFAULT_PS2: &str = "> ";
+
+/// Marks the start of a `\[...\]` run of characters that take up no room on
+/// screen, as readline does.
+pub const INVISIBLE_START: char = '\x01';
+pub const INVISIBLE_END: char = '\x02';
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `PS1`, or `PS2` while a command spans several lines, fully expanded.
+pub fn command_prompt(shell: &mut Shell, primary: bool) -> String {
+    let (name, default) = match primary {
+        true => ("PS1", DEFAULT_PS1),
+        false => ("PS2", DEFAULT_PS2),
+    };
+    let template = shell.variables.get(name).unwrap_or(default).to_string();
+
+    expand_prompt(&template, shell)
+}
+
+/// Decodes the backslash escapes of a prompt, then expands its parameters
+/// and command substitutions. `\[` and `\]` become the invisible markers.
+pub fn expand_prompt(template: &str, shell: &mut Shell) -> String {
+    let decoded = decode_escapes(template, shell);
+    expansion::expand_parameters(&decoded, shell)
+}
+
+/// Removes the invisible markers, for printing a prompt without the editor.
+pub fn strip_markers(prompt: &str) -> String {
+    prompt
+        .chars()
+        .filter(|&c| c != INVISIBLE_START && c != INVISIBLE_END)
+        .collect()
+}
+
+fn decode_escapes(template: &str, shell: &Shell) -> String {
+    let mut decoded = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            decoded.push('\\');
+            break;
+        };
+        match escape {
+            'a' => decoded.push('\x07'),
+            'e' => decoded.push('\x1b'),
+            'n' => decoded.push('\n'),
+            'r' => decoded.push('\r'),
+            '\\' => decoded.push('\\'),
+            '[' => decoded.push(INVISIBLE_START),
+            ']' => decoded.push(INVISIBLE_END),
+            '$' if unsafe { libc::geteuid() } == 0 => decoded.push('#'),
+            '$' => decoded.push('$'),
+            '?' => decoded.push_str(&shell.last_status.to_string()),
+            '!' => decoded.push_str(&shell.history.next_number().to_string()),
+            'u' => decoded.push_str(&user_name()),
+            'h' => decoded.push_str(host_name().split('.').next().unwrap_or_default()),
+            'H' => decoded.push_str(&host_name()),
+            's' => decoded.push_str(shell.script_name.rsplit('/').next().unwrap_or_default()),
+            'v' => decoded.push_str(
+                env!("CARGO_PKG_VERSION")
+                    .rsplitn(2, '.')
+                    .last()
+                    .unwrap_or_default(),
+            ),
+            'V' => decoded.push_str(env!("CARGO_PKG_VERSION")),
+            'w' => decoded.push_str(&working_directory(shell)),
+            'W' => {
+                let directory = working_directory(shell);
+                match directory.as_str() {
+                    "/" | "~" => decoded.push_str(&directory),
+                    _ => decoded.push_str(directory.rsplit('/').next().unwrap_or_default()),
+                }
+            }
+            'd' | 't' | 'T' | '@' | 'A' => decoded.push_str(&format_time(escape)),
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                decoded.extend(char::from_u32(code));
+            }
+            // Others, such as `\j` in a shell without jobs, are left as written.
+            _ => {
+                decoded.push('\\');
+                decoded.push(escape);
+            }
+        }
+    }
+
+    decoded
+}
+
+/// The current directory, with the home directory shown as `~`.
+fn working_directory(shell: &Shell) -> String {
+    abbreviate_home(&dirs::current_directory(shell), shell.variables.get("HOME"))
+}
+
+fn user_name() -> String {
+    let passwd = unsafe { libc::getpwuid(libc::geteuid()) };
+    if passwd.is_null() {
+        return env::var("USER").unwrap_or_default();
+    }
+
+    unsafe { CStr::from_ptr((*passwd).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn host_name() -> String {
+    let mut buffer = [0u8; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) } < 0 {
+        return String::new();
+    }
+
+    CStr::from_bytes_until_nul(&buffer)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Formats the local time for `\d`, `\t`, `\T`, `\@` and `\A`.
+fn format_time(escape: char) -> String {
+    let mut time: libc::tm = unsafe { mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut time);
+    }
+
+    let hour12 = match time.tm_hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    match escape {
+        'd' => format!(
+            "{} {} {:02}",
+            WEEKDAYS[time.tm_wday as usize % 7],
+            MONTHS[time.tm_mon as usize % 12],
+            time.tm_mday
+        ),
+        't' => format!("{:02}:{:02}:{:02}", time.tm_hour, time.tm_min, time.tm_sec),
+        'T' => format!("{:02}:{:02}:{:02}", hour12, time.tm_min, time.tm_sec),
+        '@' => format!(
+            "{:02}:{:02} {}",
+            hour12,
+            time.tm_min,
+            if time.tm_hour < 12 { "AM" } else { "PM" }
+        ),
+        _ => format!("{:02}:{:02}", time.tm_hour, time.tm_min),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_escapes_for_markers_and_status() {
+        let mut shell = Shell::new();
+        shell.last_status = 3;
+        let input = r"\[\e[32m\]\?\[\e[0m\] \101\q";
+        let expected = "\x01\x1b[32m\x023\x01\x1b[0m\x02 A\\q";
+
+        let result = decode_escapes(input, &shell);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_abbreviate_home() {
+        assert_eq!(abbreviate_home("/home/me", Some("/home/me")), "~");
+        assert_eq!(abbreviate_home("/home/me/src", Some("/home/me")), "~/src");
+        assert_eq!(
+            abbreviate_home("/home/melissa", Some("/home/me")),
+            "/home/melissa"
+        );
+        assert_eq!(abbreviate_home("/tmp", None), "/tmp");
+    }
+
+    #[test]
+    fn test_expand_prompt_substitutes_variables() {
+        let mut shell = Shell::new();
+        shell.variables.set("NAME", "dev");
+        let input = r"[$NAME]\\ ";
+        let expected = r"[dev]\ ";
+
+        let result = expand_prompt(input, &mut shell);
+        assert_eq!(result, expected);
+    }
+}
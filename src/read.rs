@@ -0,0 +1,401 @@
+use crate::shell::Shell;
+use crate::signals;
+use crate::terminal::ReadMode;
+use crate::utils::error_message;
+use crate::variables::{is_valid_name, Assignment};
+use std::io;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "read: usage: read [-rs] [-a array] [-d delim] [-n nchars] [-N nchars] \
+                     [-p prompt] [-t timeout] [-u fd] [name ...]\n";
+const DEFAULT_IFS: &str = " \t\n";
+/// The status for a timeout, which bash reports as if `SIGALRM` arrived.
+const TIMED_OUT: i32 = 128 + libc::SIGALRM;
+
+#[derive(Debug, PartialEq)]
+struct ReadOptions {
+    raw: bool,
+    silent: bool,
+    prompt: Option<String>,
+    timeout: Option<f64>,
+    /// How many characters to read, and whether the delimiter is ignored.
+    count: Option<(usize, bool)>,
+    delimiter: u8,
+    array: Option<String>,
+    fd: libc::c_int,
+    names: Vec<String>,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            raw: false,
+            silent: false,
+            prompt: None,
+            timeout: None,
+            count: None,
+            delimiter: b'\n',
+            array: None,
+            fd: libc::STDIN_FILENO,
+            names: Vec::new(),
+        }
+    }
+}
+
+/// Implements `read`, splitting a line of input into fields with `IFS` and
+/// assigning them to the given names, the last taking whatever is left.
+/// Without names the whole line goes to `REPLY`. Returns the status, which
+/// is nonzero at end of file or on a timeout even if some input was read.
+pub fn read(args: &[String], shell: &mut Shell) -> Result<i32, String> {
+    let options = parse_options(args)?;
+    if unsafe { libc::fcntl(options.fd, libc::F_GETFD) } < 0 {
+        let error = io::Error::last_os_error();
+        eprintln!("read: {}: invalid file descriptor: {}", options.fd, error_message(&error));
+        return Ok(1);
+    }
+
+    if options.timeout == Some(0.0) {
+        let ready = wait_for_input(options.fd, Duration::ZERO);
+        return Ok(if ready { 0 } else { 1 });
+    }
+
+    let is_terminal = unsafe { libc::isatty(options.fd) } == 1;
+    if let Some(prompt) = options.prompt.as_ref().filter(|_| is_terminal) {
+        eprint!("{prompt}");
+    }
+
+    let by_character = options.count.is_some() || options.delimiter != b'\n';
+    let _mode = match is_terminal && (options.silent || by_character) {
+        true => ReadMode::enable(options.fd, options.silent, by_character).ok(),
+        false => None,
+    };
+
+    let (input, status) = read_input(&options);
+    if status == 128 + libc::SIGINT {
+        return Ok(status);
+    }
+
+    let ifs = shell
+        .variables
+        .get("IFS")
+        .unwrap_or(DEFAULT_IFS)
+        .to_string();
+    let assignments = if let Some(array) = &options.array {
+        let fields = split_fields(&input, ifs.as_bytes(), None);
+        vec![Assignment::array(array, &fields)]
+    } else if options.names.is_empty() {
+        let line: Vec<u8> = input.iter().map(|&(byte, _)| byte).collect();
+        vec![Assignment::scalar("REPLY", &String::from_utf8_lossy(&line))]
+    } else {
+        let fields = split_fields(&input, ifs.as_bytes(), Some(options.names.len()));
+        options
+            .names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| Assignment::scalar(name, fields.get(index).map_or("", String::as_str)))
+            .collect()
+    };
+    for assignment in &assignments {
+        if let Err(message) = shell.variables.assign(assignment) {
+            eprintln!("read: {message}");
+            return Ok(1);
+        }
+    }
+
+    Ok(status)
+}
+
+fn parse_options(args: &[String]) -> Result<ReadOptions, String> {
+    let mut options = ReadOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            options.names.push(arg.clone());
+            break;
+        };
+
+        for (index, flag) in flags.char_indices() {
+            match flag {
+                'r' => options.raw = true,
+                's' => options.silent = true,
+                'p' | 't' | 'n' | 'N' | 'd' | 'a' | 'u' => {
+                    let rest = &flags[index + 1..];
+                    let value = match rest.is_empty() {
+                        true => args.next().ok_or_else(|| {
+                            format!("read: -{flag}: option requires an argument\n{USAGE}")
+                        })?,
+                        false => rest,
+                    };
+                    set_option(&mut options, flag, value)?;
+                    break;
+                }
+                _ => return Err(format!("read: -{flag}: invalid option\n{USAGE}")),
+            }
+        }
+    }
+    options.names.extend(args.cloned());
+
+    let invalid = options
+        .names
+        .iter()
+        .chain(&options.array)
+        .find(|name| !is_valid_name(name));
+    match invalid {
+        Some(name) => Err(format!("read: `{name}': not a valid identifier\n")),
+        None => Ok(options),
+    }
+}
+
+fn set_option(options: &mut ReadOptions, flag: char, value: &str) -> Result<(), String> {
+    match flag {
+        'p' => options.prompt = Some(value.to_string()),
+        'd' => options.delimiter = value.bytes().next().unwrap_or(0),
+        'a' => options.array = Some(value.to_string()),
+        't' => {
+            let timeout = value
+                .parse::<f64>()
+                .ok()
+                .filter(|timeout| *timeout >= 0.0 && timeout.is_finite())
+                .ok_or_else(|| format!("read: {value}: invalid timeout specification\n"))?;
+            options.timeout = Some(timeout);
+        }
+        'n' | 'N' => {
+            let count = value
+                .parse::<usize>()
+                .map_err(|_| format!("read: {value}: invalid number\n"))?;
+            options.count = Some((count, flag == 'N'));
+        }
+        _ => {
+            options.fd = value
+                .parse()
+                .map_err(|_| format!("read: {value}: invalid file descriptor specification\n"))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads up to the delimiter a byte at a time, so that nothing past it is
+/// taken from the file descriptor. Unless `-r` was given, backslash quotes
+/// the next byte and backslash-newline continues the line. Returns the
+/// bytes read, each marked if it was quoted, and the status.
+fn read_input(options: &ReadOptions) -> (Vec<(u8, bool)>, i32) {
+    let deadline = options
+        .timeout
+        .map(|timeout| Instant::now() + Duration::from_secs_f64(timeout));
+
+    let mut input: Vec<(u8, bool)> = Vec::new();
+    let mut characters = 0;
+    // Continuation bytes still to come for the current UTF-8 character.
+    let mut pending = 0;
+    let mut escaped = false;
+    signals::take_interrupt();
+    loop {
+        if pending == 0 && options.count.is_some_and(|(count, _)| characters >= count) {
+            return (input, 0);
+        }
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !wait_for_input(options.fd, remaining) {
+                return (input, TIMED_OUT);
+            }
+        }
+
+        let mut byte = 0u8;
+        let count = unsafe { libc::read(options.fd, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        match count {
+            1 => {}
+            0 => return (input, 1),
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {
+                if signals::take_interrupt() {
+                    return (input, 128 + libc::SIGINT);
+                }
+                continue;
+            }
+            _ => return (input, 1),
+        }
+
+        let exact = options.count.is_some_and(|(_, exact)| exact);
+        let quoted = escaped;
+        if escaped {
+            escaped = false;
+            if byte == b'\n' {
+                continue;
+            }
+        } else if byte == options.delimiter && !exact {
+            return (input, 0);
+        } else if byte == b'\\' && !options.raw {
+            escaped = true;
+            continue;
+        }
+
+        input.push((byte, quoted));
+        match byte {
+            0x80..=0xbf if pending > 0 => pending -= 1,
+            0xc0..=0xdf => pending = 1,
+            0xe0..=0xef => pending = 2,
+            0xf0..=0xf7 => pending = 3,
+            _ => pending = 0,
+        }
+        if pending == 0 {
+            characters += 1;
+        }
+    }
+}
+
+/// Waits until `fd` has input, returning `false` if `timeout` passes first.
+fn wait_for_input(fd: libc::c_int, timeout: Duration) -> bool {
+    let mut poll = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let milliseconds = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+
+    let ready = unsafe { libc::poll(&mut poll, 1, milliseconds) };
+    ready > 0
+}
+
+/// Splits input into fields at unquoted `IFS` characters. Runs of `IFS`
+/// whitespace count as one separator and are trimmed from both ends. With
+/// a `limit`, the last field takes the rest of the input unsplit.
+fn split_fields(input: &[(u8, bool)], ifs: &[u8], limit: Option<usize>) -> Vec<String> {
+    let is_separator = |index: usize| {
+        let (byte, quoted) = input[index];
+        !quoted && ifs.contains(&byte)
+    };
+    let is_blank =
+        |index: usize| is_separator(index) && matches!(input[index].0, b' ' | b'\t' | b'\n');
+    let text = |range: &[(u8, bool)]| {
+        let bytes: Vec<u8> = range.iter().map(|&(byte, _)| byte).collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position < input.len() && is_blank(position) {
+        position += 1;
+    }
+    while position < input.len() {
+        if limit.is_some_and(|limit| fields.len() + 1 == limit) {
+            let mut end = input.len();
+            while end > position && is_blank(end - 1) {
+                end -= 1;
+            }
+            fields.push(text(&input[position..end]));
+            break;
+        }
+
+        let start = position;
+        while position < input.len() && !is_separator(position) {
+            position += 1;
+        }
+        fields.push(text(&input[start..position]));
+
+        while position < input.len() && is_blank(position) {
+            position += 1;
+        }
+        if position < input.len() && is_separator(position) {
+            position += 1;
+            while position < input.len() && is_blank(position) {
+                position += 1;
+            }
+        }
+    }
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unquoted(text: &str) -> Vec<(u8, bool)> {
+        text.bytes().map(|byte| (byte, false)).collect()
+    }
+
+    #[test]
+    fn test_split_fields_last_name_takes_the_rest() {
+        let input = unquoted("  one  two three  ");
+        let expected = vec!["one".to_string(), "two three".to_string()];
+
+        let result = split_fields(&input, b" \t\n", Some(2));
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_split_fields_with_non_blank_separators() {
+        let input = unquoted("a,,b , c");
+        let expected = vec!["a", "", "b", "c"];
+
+        let result = split_fields(&input, b", ", None);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_split_fields_keeps_quoted_separators() {
+        let mut input = unquoted("a b c");
+        input[1].1 = true;
+        let expected = vec!["a b", "c"];
+
+        let result = split_fields(&input, b" ", None);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_parse_options_combined_flags() {
+        let args: Vec<String> = ["-rsn3", "-p", "> ", "-d:", "first", "second"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let expected = ReadOptions {
+            raw: true,
+            silent: true,
+            prompt: Some("> ".to_string()),
+            count: Some((3, false)),
+            delimiter: b':',
+            names: vec!["first".to_string(), "second".to_string()],
+            ..ReadOptions::default()
+        };
+
+        let result = parse_options(&args);
+        assert_eq!(result, Ok(expected));
+    }
+
+    #[test]
+    fn test_read_from_pipe_until_end_of_file() {
+        let mut fds = [0; 2];
+        unsafe {
+            libc::pipe(fds.as_mut_ptr());
+            let input = b"x\\ y z\\\nw\ntail";
+            libc::write(fds[1], input.as_ptr() as *const libc::c_void, input.len());
+            libc::close(fds[1]);
+        }
+        let mut shell = Shell::new();
+        let args = |names: &[&str]| -> Vec<String> {
+            ["-u".to_string(), fds[0].to_string()]
+                .into_iter()
+                .chain(names.iter().map(|name| name.to_string()))
+                .collect()
+        };
+
+        assert_eq!(read(&args(&["A", "B"]), &mut shell), Ok(0));
+        assert_eq!(shell.variables.get("A"), Some("x y"));
+        assert_eq!(shell.variables.get("B"), Some("zw"));
+
+        assert_eq!(read(&args(&[]), &mut shell), Ok(1));
+        assert_eq!(shell.variables.get("REPLY"), Some("tail"));
+        unsafe { libc::close(fds[0]) };
+    }
+
+    #[test]
+    fn test_read_from_closed_descriptor_fails() {
+        let mut shell = Shell::new();
+        let args = vec!["-u".to_string(), "987".to_string()];
+
+        assert_eq!(read(&args, &mut shell), Ok(1));
+    }
+}
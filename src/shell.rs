@@ -0,0 +1,618 @@
+use crate::alias;
+use crate::alias::Aliases;
+use crate::command::Executable;
+use crate::completion::CompletionSpec;
+use crate::expansion;
+use crate::history::{History, HistoryConfig};
+use crate::options::ShellOptions;
+use crate::parser::{self, Connector};
+use crate::signals;
+use crate::trap::{TrapCondition, Traps};
+use crate::variables::Variables;
+use crate::utils::{error_message, home_path};
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::os::fd::FromRawFd;
+use std::process;
+
+/// State that outlives a single command line.
+#[derive(Debug, Default)]
+pub struct Shell {
+    pub last_status: i32,
+    pub interactive: bool,
+    /// `$0`: the script being run, or the shell's own name.
+    pub script_name: String,
+    /// `$$`, which subshells inherit rather than report their own.
+    pub pid: u32,
+    pub positional: Vec<String>,
+    pub variables: Variables,
+    /// Function names mapped to the source text of their bodies.
+    pub functions: BTreeMap<String, String>,
+    pub aliases: Aliases,
+    pub traps: Traps,
+    pub options: ShellOptions,
+    pub history: History,
+    /// `pushd`'s saved directories, below the current one.
+    pub directory_stack: Vec<String>,
+    /// Completion specs registered with `complete`, by command name.
+    pub completions: BTreeMap<String, CompletionSpec>,
+    /// Set by `return` to unwind to the enclosing function or sourced file.
+    pub returning: bool,
+    /// Set when expansion failed under `set -u`, so that the command isn't
+    /// run.
+    pub expansion_failed: bool,
+    call_depth: usize,
+    /// How many `&&`/`||` conditions and `!` pipelines the running command
+    /// is inside; `set -e` and the ERR trap ignore failures there.
+    condition_depth: usize,
+    running_trap: bool,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Shell {
+            script_name: "shell_shell".to_string(),
+            pid: process::id(),
+            variables: Variables::from_environment(),
+            ..Shell::default()
+        }
+    }
+
+    /// Runs a whole script or `-c` string, returning the last status.
+    pub fn run_script(&mut self, source: &str) -> i32 {
+        if !parser::is_complete(source) {
+            eprintln!("{}: syntax error: unexpected end of file", self.script_name);
+            self.last_status = 2;
+            return self.last_status;
+        }
+
+        // Unlike function bodies, scripts echo their commands under `set -v`.
+        for command in parser::split_commands(source) {
+            if self.options.verbose {
+                eprintln!("{command}");
+            }
+            self.run_line(&command);
+        }
+
+        self.last_status
+    }
+
+    /// Runs each command in `source`, stopping early on `return`.
+    pub fn run_source(&mut self, source: &str) -> i32 {
+        for command in parser::split_commands(source) {
+            self.run_line(&command);
+            if self.returning {
+                break;
+            }
+        }
+
+        self.last_status
+    }
+
+    pub fn call_function(&mut self, name: &str, args: &[String]) -> i32 {
+        let body = self.functions[name].clone();
+        let positional = std::mem::replace(&mut self.positional, args.to_vec());
+
+        let status = self.run_nested(&body);
+
+        self.positional = positional;
+        status
+    }
+
+    /// Runs a sourced file in the current shell. Arguments, if any, replace
+    /// the positional parameters until the file is done.
+    pub fn source(&mut self, source: &str, args: &[String]) -> i32 {
+        if args.is_empty() {
+            return self.run_nested(source);
+        }
+
+        let positional = std::mem::replace(&mut self.positional, args.to_vec());
+        let status = self.run_nested(source);
+        self.positional = positional;
+        status
+    }
+
+    /// Sources a startup file such as `~/.shell_shellrc`, silently doing
+    /// nothing when it doesn't exist or is in the home directory and `HOME`
+    /// isn't set.
+    pub fn source_startup_file(&mut self, path: &str) {
+        let Some(path) = home_path(path) else {
+            return;
+        };
+        if let Ok(source) = fs::read_to_string(&path) {
+            self.source(&source, &[]);
+        }
+    }
+
+    /// Runs `PROMPT_COMMAND`, each element if it is an array, and the
+    /// `precmd` hooks before a primary prompt. `PROMPT_COMMAND` runs as if
+    /// typed, so `return` isn't allowed in it.
+    pub fn run_prompt_hooks(&mut self) {
+        let status = self.last_status;
+        for command in self.variables.elements("PROMPT_COMMAND") {
+            self.run_source(&command);
+        }
+        self.last_status = status;
+
+        self.run_hook("precmd", &[]);
+    }
+
+    /// Calls the function `name`, if there is one, and then each function
+    /// listed in `name_functions`, zsh style. `$?` is left as it was.
+    pub fn run_hook(&mut self, name: &str, args: &[String]) {
+        let listed = self.variables.elements(&format!("{name}_functions"));
+        let functions: Vec<String> = std::iter::once(name.to_string())
+            .chain(listed.iter().flat_map(|entry| entry.split_whitespace()).map(str::to_string))
+            .collect();
+
+        let status = self.last_status;
+        for function in functions {
+            if self.functions.contains_key(&function) {
+                self.call_function(&function, args);
+            }
+        }
+        self.last_status = status;
+    }
+
+    /// Runs `source` in a forked copy of the shell and returns what it wrote
+    /// to stdout with trailing newlines removed, as `$(...)` does.
+    pub fn command_substitution(&mut self, source: &str) -> String {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+            eprintln!("{}: {}", self.script_name, error_message(&io::Error::last_os_error()));
+            return String::new();
+        }
+        io::stdout().flush().ok();
+
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            unsafe {
+                libc::close(fds[0]);
+                libc::dup2(fds[1], libc::STDOUT_FILENO);
+                libc::close(fds[1]);
+            }
+            self.interactive = false;
+            self.traps.take(TrapCondition::Exit);
+            let status = self.run_script(source);
+            io::stdout().flush().ok();
+            unsafe { libc::_exit(status) };
+        }
+
+        unsafe { libc::close(fds[1]) };
+        if pid < 0 {
+            eprintln!("{}: {}", self.script_name, error_message(&io::Error::last_os_error()));
+            unsafe { libc::close(fds[0]) };
+            return String::new();
+        }
+
+        let mut output = Vec::new();
+        let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+        reader.read_to_end(&mut output).ok();
+        self.last_status = wait_for(pid);
+
+        String::from_utf8_lossy(&output)
+            .trim_end_matches('\n')
+            .to_string()
+    }
+
+    /// `return` is only valid inside a function or a sourced file.
+    pub fn can_return(&self) -> bool {
+        self.call_depth > 0
+    }
+
+    fn run_nested(&mut self, source: &str) -> i32 {
+        self.call_depth += 1;
+        self.run_source(source);
+        self.call_depth -= 1;
+        self.returning = false;
+
+        self.last_status
+    }
+
+    pub fn run_line(&mut self, line: &str) -> i32 {
+        if line.trim().is_empty() {
+            return self.last_status;
+        }
+
+        // As in bash, only interactive shells expand aliases. An alias may
+        // stand for several commands.
+        if self.interactive {
+            let expanded = alias::expand_aliases(line, &self.aliases);
+            if expanded != line {
+                for command in parser::split_commands(&expanded) {
+                    self.run_command(&command);
+                }
+                return self.last_status;
+            }
+        }
+
+        self.run_command(line)
+    }
+
+    /// Runs an `&&`/`||` list of pipelines. A failure that isn't being
+    /// tested runs the ERR trap and, under `set -e`, exits the shell.
+    fn run_command(&mut self, line: &str) -> i32 {
+        let list = match parser::split_list(line) {
+            Ok(list) => list,
+            Err(message) => {
+                eprintln!("{}: {}", self.script_name, message);
+                self.last_status = 2;
+                return self.last_status;
+            }
+        };
+        if self.options.noexec && !self.interactive {
+            return self.last_status;
+        }
+
+        for (index, (connector, pipeline)) in list.iter().enumerate() {
+            let skipped = match connector {
+                Some(Connector::And) => self.last_status != 0,
+                Some(Connector::Or) => self.last_status == 0,
+                None => false,
+            };
+            if skipped {
+                continue;
+            }
+
+            let tested = index + 1 < list.len() || pipeline.negated;
+            self.condition_depth += usize::from(tested);
+            let status = self.run_pipeline(&pipeline.commands);
+            self.condition_depth -= usize::from(tested);
+
+            self.last_status = match pipeline.negated {
+                true => i32::from(status == 0),
+                false => status,
+            };
+            if status != 0 && !tested && self.condition_depth == 0 {
+                self.run_trap(TrapCondition::Err);
+                if self.options.errexit {
+                    self.exit(status);
+                }
+            }
+            if self.returning {
+                break;
+            }
+        }
+
+        self.last_status
+    }
+
+    /// Runs the commands of a pipeline in forked copies of the shell, each
+    /// reading the output of the one before. A lone command runs in the
+    /// shell itself.
+    fn run_pipeline(&mut self, commands: &[String]) -> i32 {
+        if let [command] = commands {
+            return self.run_simple(command);
+        }
+
+        io::stdout().flush().ok();
+        let mut input: Option<libc::c_int> = None;
+        let mut pids = Vec::new();
+        for (index, command) in commands.iter().enumerate() {
+            let last = index + 1 == commands.len();
+            let mut fds = [0; 2];
+            if !last && unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+                eprintln!("{}: {}", self.script_name, error_message(&io::Error::last_os_error()));
+                break;
+            }
+
+            let pid = unsafe { libc::fork() };
+            if pid == 0 {
+                unsafe {
+                    if let Some(input) = input {
+                        libc::dup2(input, libc::STDIN_FILENO);
+                        libc::close(input);
+                    }
+                    if !last {
+                        libc::close(fds[0]);
+                        libc::dup2(fds[1], libc::STDOUT_FILENO);
+                        libc::close(fds[1]);
+                    }
+                }
+                signals::restore_broken_pipe();
+                self.interactive = false;
+                self.traps.take(TrapCondition::Exit);
+                let status = self.run_simple(command);
+                io::stdout().flush().ok();
+                unsafe { libc::_exit(status) };
+            }
+
+            if let Some(input) = input.take() {
+                unsafe { libc::close(input) };
+            }
+            if !last {
+                unsafe { libc::close(fds[1]) };
+                input = Some(fds[0]);
+            }
+            if pid < 0 {
+                eprintln!("{}: {}", self.script_name, error_message(&io::Error::last_os_error()));
+                break;
+            }
+            pids.push(pid);
+        }
+        if let Some(input) = input {
+            unsafe { libc::close(input) };
+        }
+
+        let statuses: Vec<i32> = pids.into_iter().map(wait_for).collect();
+        let status = match self.options.pipefail {
+            true => statuses.iter().rev().find(|&&status| status != 0),
+            false => statuses.last(),
+        };
+        status.copied().unwrap_or(0)
+    }
+
+    fn run_simple(&mut self, line: &str) -> i32 {
+        self.run_trap(TrapCondition::Debug);
+
+        // Expansions outside any command, such as the prompt's, may have
+        // failed since the last one ran.
+        self.expansion_failed = false;
+        let command = parser::parse_command(line, self);
+        self.last_status = match std::mem::take(&mut self.expansion_failed) {
+            true if !self.interactive => self.exit(127),
+            true => 127,
+            false => command.execute(self),
+        };
+        self.run_pending_traps();
+
+        self.last_status
+    }
+
+    /// Prints a command for `set -x`.
+    pub fn trace(&mut self, command: &str) {
+        eprintln!("{}", self.traced(command));
+    }
+
+    /// The line `set -x` prints for a command, after the expanded `PS4`.
+    fn traced(&mut self, command: &str) -> String {
+        let ps4 = self.variables.get("PS4").unwrap_or("+ ").to_string();
+        let failed = self.expansion_failed;
+        let ps4 = expansion::expand_parameters(&ps4, self);
+        self.expansion_failed = failed;
+        format!("{ps4}{command}")
+    }
+
+    /// Runs the actions of trapped signals that arrived since the last check.
+    pub fn run_pending_traps(&mut self) {
+        for signal in self.traps.caught_signals() {
+            if signals::take_pending(signal) {
+                self.run_trap(TrapCondition::Signal(signal));
+            }
+        }
+    }
+
+    pub fn load_history(&mut self) {
+        let config = HistoryConfig::from_variables(&self.variables);
+        self.history.load(&config);
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        let config = HistoryConfig::from_variables(&self.variables);
+        self.history.add(line, &config);
+    }
+
+    /// Runs the EXIT trap, if any, and terminates the shell.
+    pub fn exit(&mut self, status: i32) -> ! {
+        if let Some(action) = self.traps.take(TrapCondition::Exit) {
+            self.last_status = status;
+            self.run_source(&action);
+        }
+        if self.interactive {
+            let config = HistoryConfig::from_variables(&self.variables);
+            if let Err(e) = self.history.trim_file(&config) {
+                eprintln!("history: {}", error_message(&e));
+            }
+        }
+
+        io::stdout().flush().ok();
+        process::exit(status);
+    }
+
+    /// Trap actions don't trigger further traps and leave `$?` untouched.
+    fn run_trap(&mut self, condition: TrapCondition) {
+        if self.running_trap {
+            return;
+        }
+        let action = match self.traps.action(condition) {
+            Some(action) if !action.is_empty() => action.to_string(),
+            _ => return,
+        };
+
+        let status = self.last_status;
+        self.running_trap = true;
+        self.run_source(&action);
+        self.running_trap = false;
+        self.last_status = status;
+    }
+}
+
+/// Waits for a forked child, returning its status the way `$?` shows it.
+fn wait_for(pid: libc::pid_t) -> i32 {
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, 0) } < 0 {
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return 1;
+        }
+    }
+
+    if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        libc::WEXITSTATUS(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `script` in a forked copy of a new shell, so that `set -e` and
+    /// `exit` end the child rather than the tests, and returns its status.
+    fn forked_status(script: &str) -> i32 {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let status = Shell::new().run_script(script);
+            unsafe { libc::_exit(status) };
+        }
+
+        wait_for(pid)
+    }
+
+    #[test]
+    fn test_interrupt_ends_non_interactive_shell() {
+        let script = "sh -c 'kill -INT $PPID; sleep 1'; exit 3";
+        let interrupted_status = |interactive: bool| {
+            let pid = unsafe { libc::fork() };
+            if pid == 0 {
+                signals::init_shell_signals(interactive);
+                let status = Shell::new().run_script(script);
+                unsafe { libc::_exit(status) };
+            }
+
+            wait_for(pid)
+        };
+
+        assert_eq!(interrupted_status(false), 128 + libc::SIGINT);
+        assert_eq!(interrupted_status(true), 3);
+    }
+
+    #[test]
+    fn test_errexit_exits_on_untested_failure() {
+        assert_eq!(forked_status("set -e; false; exit 3"), 1);
+        assert_eq!(forked_status("set -e; true; exit 3"), 3);
+        assert_eq!(forked_status("set -u; echo $SHELL_TEST_UNSET; exit 3"), 127);
+    }
+
+    #[test]
+    fn test_nounset_only_fails_commands_for_their_own_expansions() {
+        let mut shell = Shell::new();
+        shell.interactive = true;
+        shell.run_script("set -u; PS1='$SHELL_TEST_UNSET> '");
+        crate::prompt::command_prompt(&mut shell, true);
+        assert_eq!(shell.run_script("true"), 0);
+
+        let input = "set -u; PS4='$SHELL_TEST_UNSET+ '; set -x; true; exit 3";
+        assert_eq!(forked_status(input), 3);
+    }
+
+    #[test]
+    fn test_errexit_ignores_conditions() {
+        let input = "set -e; false || true; false && true; ! true; exit 3";
+        assert_eq!(forked_status(input), 3);
+
+        let input = "set -e; f() { false; return 0; }; f || exit 4; exit 5";
+        assert_eq!(forked_status(input), 5);
+    }
+
+    #[test]
+    fn test_err_trap_runs_on_untested_failure() {
+        assert_eq!(forked_status("trap 'exit 9' ERR; false; exit 3"), 9);
+        assert_eq!(forked_status("trap 'exit 9' ERR; false || true; exit 3"), 3);
+    }
+
+    #[test]
+    fn test_and_or_lists_and_negation() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run_script("false && exit 4 || true"), 0);
+        assert_eq!(shell.run_script("true || false && false"), 1);
+        assert_eq!(shell.run_script("! true"), 1);
+        assert_eq!(shell.condition_depth, 0);
+    }
+
+    #[test]
+    fn test_pipeline_status_with_pipefail() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run_script("exit 2 | true"), 0);
+        assert_eq!(shell.run_script("true | exit 2"), 2);
+        assert_eq!(shell.run_script("set -o pipefail; exit 2 | exit 3 | true"), 3);
+        assert_eq!(shell.run_script("! exit 2 | true"), 0);
+    }
+
+    #[test]
+    fn test_noexec_skips_commands() {
+        let mut shell = Shell::new();
+        shell.run_script("set -n; SHELL_TEST_NOEXEC=1");
+
+        assert_eq!(shell.variables.get("SHELL_TEST_NOEXEC"), None);
+    }
+
+    #[test]
+    fn test_prompt_command_as_string_and_array() {
+        let mut shell = Shell::new();
+        shell.run_script("PROMPT_COMMAND='LOG+=\" one\"; LOG+=\" two\"'");
+        shell.run_prompt_hooks();
+        assert_eq!(shell.variables.get("LOG"), Some(" one two"));
+
+        shell.run_script("LOG=; PROMPT_COMMAND=('LOG+=\" first\"' 'LOG+=\" second\"')");
+        shell.run_prompt_hooks();
+        assert_eq!(shell.variables.get("LOG"), Some(" first second"));
+    }
+
+    #[test]
+    fn test_prompt_command_rejects_return() {
+        let mut shell = Shell::new();
+        shell.run_script("PROMPT_COMMAND='return 3; LOG=after'");
+        shell.last_status = 5;
+        shell.run_prompt_hooks();
+
+        assert_eq!(shell.variables.get("LOG"), Some("after"));
+        assert_eq!(shell.last_status, 5);
+        assert!(!shell.returning);
+    }
+
+    #[test]
+    fn test_hooks_run_in_order_with_arguments() {
+        let mut shell = Shell::new();
+        shell.run_script(
+            "PROMPT_COMMAND='LOG+=\" prompt\"'\n\
+             precmd() { LOG+=\" precmd\"; }\n\
+             early() { LOG+=\" early:$#\"; }\n\
+             late() { LOG+=\" late\"; }\n\
+             precmd_functions=(early late)\n\
+             preexec() { LOG+=\" preexec:$1\"; }\n\
+             preexec_functions=(early)",
+        );
+        shell.last_status = 4;
+
+        shell.run_prompt_hooks();
+        shell.run_hook("preexec", &["ls -l".to_string()]);
+
+        let expected = " prompt precmd early:0 late preexec:ls -l early:1";
+        assert_eq!(shell.variables.get("LOG"), Some(expected));
+        assert_eq!(shell.last_status, 4);
+    }
+
+    #[test]
+    fn test_chpwd_runs_after_cd() {
+        assert_eq!(forked_status("chpwd() { exit 7; }; cd /; exit 1"), 7);
+        assert_eq!(forked_status("chpwd() { exit 7; }; cd /nonexistent; exit 1"), 1);
+    }
+
+    #[test]
+    fn test_exec_redirection_is_used_by_later_commands() {
+        let path = std::env::temp_dir().join(format!("shell_exec_test_{}", process::id()));
+        let file = path.to_str().unwrap();
+        let script = format!(
+            "exec 4>{file}; echo builtin >&4; sh -c 'echo external' >&4; \
+             sh -c 'echo error >&2' 2>&4; exec 4>&-; echo closed >&4"
+        );
+
+        assert_eq!(forked_status(&script), 1);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "builtin\nexternal\nerror\n");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_trace_expands_ps4() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.traced("echo hi"), "+ echo hi");
+
+        shell.variables.set("SHELL_TEST_DEPTH", "2");
+        shell.variables.set("PS4", "+$SHELL_TEST_DEPTH> ");
+        assert_eq!(shell.traced("echo hi"), "+2> echo hi");
+    }
+}
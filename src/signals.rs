@@ -0,0 +1,252 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const SIGNAL_COUNT: usize = 65;
+
+const SIGNAL_NAMES: [(&str, i32); 29] = [
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP),
+    ("ABRT", libc::SIGABRT),
+    ("BUS", libc::SIGBUS),
+    ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("URG", libc::SIGURG),
+    ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ),
+    ("VTALRM", libc::SIGVTALRM),
+    ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH),
+    ("IO", libc::SIGIO),
+    ("SYS", libc::SIGSYS),
+];
+
+/// Whether the shell installed the interactive dispositions at startup.
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+static PENDING: [AtomicBool; SIGNAL_COUNT] = [const { AtomicBool::new(false) }; SIGNAL_COUNT];
+
+/// Signals ignored with `trap ''`, which children must go on ignoring.
+static IGNORED: [AtomicBool; SIGNAL_COUNT] = [const { AtomicBool::new(false) }; SIGNAL_COUNT];
+
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    PENDING[libc::SIGINT as usize].store(true, Ordering::SeqCst);
+}
+
+extern "C" fn handle_trapped(signal: libc::c_int) {
+    if signal == libc::SIGWINCH {
+        RESIZED.store(true, Ordering::SeqCst);
+    }
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Sets up the dispositions the shell itself runs with. An interactive
+/// shell catches SIGINT rather than ignoring it, so that a blocking read
+/// returns `EINTR` and the current line can be discarded; SIGQUIT and
+/// SIGTSTP are ignored. SIGWINCH is caught so the line editor can redraw
+/// after a resize. Other shells keep the defaults, so that an interrupt
+/// ends a script along with its foreground command.
+pub fn init_shell_signals(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::SeqCst);
+    if !interactive {
+        return;
+    }
+
+    set_handler(
+        libc::SIGINT,
+        handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+    );
+    catch_signal(libc::SIGWINCH);
+    set_handler(libc::SIGQUIT, libc::SIG_IGN);
+    set_handler(libc::SIGTSTP, libc::SIG_IGN);
+}
+
+/// Installs a handler that only records the signal, so that the trap
+/// action can run later at a safe point in the executor.
+pub fn catch_signal(signal: i32) {
+    if signal == libc::SIGINT {
+        set_handler(
+            libc::SIGINT,
+            handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    } else {
+        set_handler(
+            signal,
+            handle_trapped as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    PENDING[signal as usize].store(false, Ordering::SeqCst);
+    IGNORED[signal as usize].store(false, Ordering::SeqCst);
+}
+
+/// Ignores `signal` for `trap ''`, in the shell and in its children.
+pub fn ignore_signal(signal: i32) {
+    set_handler(signal, libc::SIG_IGN);
+    IGNORED[signal as usize].store(true, Ordering::SeqCst);
+}
+
+/// Goes back to the disposition the shell started with for `signal`.
+pub fn reset_signal(signal: i32) {
+    IGNORED[signal as usize].store(false, Ordering::SeqCst);
+    let interactive = INTERACTIVE.load(Ordering::SeqCst);
+    match signal {
+        libc::SIGINT | libc::SIGWINCH if interactive => catch_signal(signal),
+        libc::SIGQUIT | libc::SIGTSTP if interactive => set_handler(signal, libc::SIG_IGN),
+        _ => set_handler(signal, libc::SIG_DFL),
+    }
+}
+
+fn is_ignored(signal: i32) -> bool {
+    IGNORED[signal as usize].load(Ordering::SeqCst)
+}
+
+/// Returns whether `signal` arrived since the last call, clearing the flag.
+pub fn take_pending(signal: i32) -> bool {
+    PENDING
+        .get(signal as usize)
+        .is_some_and(|pending| pending.swap(false, Ordering::SeqCst))
+}
+
+/// Returns whether the terminal was resized since the last call.
+pub fn take_resize() -> bool {
+    RESIZED.swap(false, Ordering::SeqCst)
+}
+
+/// Accepts `INT`, `SIGINT` or `2`, case-insensitively for the names.
+pub fn signal_number(name: &str) -> Option<i32> {
+    if let Ok(number) = name.parse::<i32>() {
+        return (number > 0 && (number as usize) < SIGNAL_COUNT).then_some(number);
+    }
+
+    let name = name.to_ascii_uppercase();
+    let name = name.strip_prefix("SIG").unwrap_or(&name);
+    SIGNAL_NAMES
+        .iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|(_, number)| *number)
+}
+
+pub fn signal_name(signal: i32) -> String {
+    SIGNAL_NAMES
+        .iter()
+        .find(|(_, number)| *number == signal)
+        .map(|(name, _)| format!("SIG{name}"))
+        .unwrap_or_else(|| signal.to_string())
+}
+
+/// The signals a child gets back the default disposition for.
+const RESTORED_SIGNALS: [i32; 4] = [libc::SIGINT, libc::SIGQUIT, libc::SIGTSTP, libc::SIGPIPE];
+
+/// The shell's own dispositions for the signals `restore_default_signals`
+/// changes, for going back to them when an `exec` fails.
+pub struct Dispositions(Vec<(i32, libc::sigaction)>);
+
+impl Dispositions {
+    pub fn save() -> Self {
+        let saved = RESTORED_SIGNALS
+            .iter()
+            .map(|&signal| {
+                let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+                unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) };
+                (signal, action)
+            })
+            .collect();
+
+        Dispositions(saved)
+    }
+
+    pub fn restore(&self) {
+        for (signal, action) in &self.0 {
+            unsafe { libc::sigaction(*signal, action, std::ptr::null_mut()) };
+        }
+    }
+}
+
+/// Puts back the default dispositions in a forked child before it execs.
+/// Ignored signals survive `execve`, so this has to be done explicitly.
+/// That includes SIGPIPE, which the Rust runtime ignores at startup.
+/// Signals ignored with `trap ''` stay ignored, as POSIX requires.
+pub fn restore_default_signals() -> io::Result<()> {
+    for signal in RESTORED_SIGNALS {
+        if is_ignored(signal) {
+            continue;
+        }
+        if unsafe { libc::signal(signal, libc::SIG_DFL) } == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+/// Lets writes to a closed pipe kill a forked copy of the shell, as they
+/// would any other command, instead of failing with `EPIPE`.
+pub fn restore_broken_pipe() {
+    if !is_ignored(libc::SIGPIPE) {
+        set_handler(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+/// Returns whether a SIGINT arrived since the last call, clearing the flag.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::SeqCst)
+}
+
+fn set_handler(signal: libc::c_int, handler: libc::sighandler_t) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = 0;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, std::ptr::null_mut());
+    }
+}
+
+pub fn signal_description(signal: i32) -> String {
+    let description = match signal {
+        libc::SIGHUP => "Hangup",
+        libc::SIGINT => "Interrupt",
+        libc::SIGQUIT => "Quit",
+        libc::SIGILL => "Illegal instruction",
+        libc::SIGTRAP => "Trace/breakpoint trap",
+        libc::SIGABRT => "Aborted",
+        libc::SIGBUS => "Bus error",
+        libc::SIGFPE => "Floating point exception",
+        libc::SIGKILL => "Killed",
+        libc::SIGUSR1 => "User defined signal 1",
+        libc::SIGSEGV => "Segmentation fault",
+        libc::SIGUSR2 => "User defined signal 2",
+        libc::SIGPIPE => "Broken pipe",
+        libc::SIGALRM => "Alarm clock",
+        libc::SIGTERM => "Terminated",
+        libc::SIGSTOP => "Stopped (signal)",
+        libc::SIGTSTP => "Stopped",
+        libc::SIGXCPU => "CPU time limit exceeded",
+        libc::SIGXFSZ => "File size limit exceeded",
+        libc::SIGSYS => "Bad system call",
+        _ => return format!("Unknown signal {signal}"),
+    };
+
+    description.to_string()
+}
@@ -0,0 +1,311 @@
+use std::io;
+use std::mem;
+
+/// Puts the terminal into raw mode for as long as it is alive, restoring
+/// the original settings on drop.
+pub struct RawMode {
+    original: libc::termios,
+    raw: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Output processing stays on so that "\n" still moves to column 0.
+        let mut raw = original;
+        raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        raw.c_cflag |= libc::CS8;
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawMode { original, raw })
+    }
+
+    /// Puts the original settings back while `f` runs, for completion
+    /// functions and the commands they start.
+    pub fn suspended<T>(&self, f: impl FnOnce() -> T) -> T {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original) };
+        let result = f();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.raw) };
+
+        result
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+/// Turns off echo, line buffering or both on a terminal for as long as it
+/// is alive, for `read -s` and `read -n`.
+pub struct ReadMode {
+    fd: libc::c_int,
+    original: libc::termios,
+}
+
+impl ReadMode {
+    pub fn enable(fd: libc::c_int, silent: bool, by_character: bool) -> io::Result<ReadMode> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut original) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut settings = original;
+        if silent {
+            settings.c_lflag &= !libc::ECHO;
+        }
+        if by_character {
+            settings.c_lflag &= !libc::ICANON;
+            settings.c_cc[libc::VMIN] = 1;
+            settings.c_cc[libc::VTIME] = 0;
+        }
+        if unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, &settings) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(ReadMode { fd, original })
+    }
+}
+
+impl Drop for ReadMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+pub fn width() -> usize {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result < 0 || size.ws_col == 0 {
+        80
+    } else {
+        size.ws_col as usize
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    AltBackspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    WordLeft,
+    WordRight,
+    Home,
+    End,
+    Unknown,
+}
+
+pub enum Input {
+    Key(Key),
+    Eof,
+    /// A signal interrupted the read before a key arrived.
+    Interrupted,
+}
+
+/// How long to wait for the rest of an escape sequence before treating
+/// ESC as a key of its own.
+const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+pub fn read_key() -> Input {
+    let byte = match read_byte(-1) {
+        Ok(Some(byte)) => byte,
+        Ok(None) => return Input::Eof,
+        Err(_) => return Input::Interrupted,
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape_sequence(),
+        0x00..=0x1f => Key::Ctrl((byte + b'a' - 1) as char),
+        _ => read_utf8(byte).map(Key::Char).unwrap_or(Key::Unknown),
+    };
+
+    Input::Key(key)
+}
+
+fn read_escape_sequence() -> Key {
+    let Ok(Some(byte)) = read_byte(ESCAPE_TIMEOUT_MS) else {
+        return Key::Escape;
+    };
+
+    match byte {
+        b'[' | b'O' => {}
+        0x7f | 0x08 => return Key::AltBackspace,
+        b'b' => return Key::WordLeft,
+        b'f' => return Key::WordRight,
+        _ => return Key::Alt(byte as char),
+    }
+
+    let mut parameters = String::new();
+    loop {
+        match read_byte(ESCAPE_TIMEOUT_MS) {
+            Ok(Some(byte)) if byte.is_ascii_digit() || byte == b';' => {
+                parameters.push(byte as char);
+            }
+            Ok(Some(byte)) => return escape_sequence_key(&parameters, byte),
+            _ => return Key::Unknown,
+        }
+    }
+}
+
+fn escape_sequence_key(parameters: &str, last: u8) -> Key {
+    let ctrl = parameters.ends_with(";5");
+    match (parameters, last) {
+        (_, b'A') => Key::Up,
+        (_, b'B') => Key::Down,
+        (_, b'C') if ctrl => Key::WordRight,
+        (_, b'D') if ctrl => Key::WordLeft,
+        (_, b'C') => Key::Right,
+        (_, b'D') => Key::Left,
+        (_, b'H') | ("1" | "7", b'~') => Key::Home,
+        (_, b'F') | ("4" | "8", b'~') => Key::End,
+        ("3", b'~') => Key::Delete,
+        _ => Key::Unknown,
+    }
+}
+
+/// Reads the continuation bytes of a multi-byte UTF-8 character.
+fn read_utf8(first: u8) -> Option<char> {
+    let length = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return None,
+    };
+
+    let mut bytes = vec![first];
+    while bytes.len() < length {
+        bytes.push(read_byte(ESCAPE_TIMEOUT_MS).ok()??);
+    }
+
+    std::str::from_utf8(&bytes).ok()?.chars().next()
+}
+
+/// Reads one byte from stdin, waiting at most `timeout_ms` (-1 waits
+/// forever). Returns `None` on EOF or timeout and an error on `EINTR`.
+fn read_byte(timeout_ms: i32) -> io::Result<Option<u8>> {
+    let mut poll_fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) } {
+        0 => return Ok(None),
+        count if count < 0 => return Err(io::Error::last_os_error()),
+        _ => {}
+    }
+
+    let mut byte = 0u8;
+    let count = unsafe {
+        libc::read(
+            libc::STDIN_FILENO,
+            &mut byte as *mut u8 as *mut libc::c_void,
+            1,
+        )
+    };
+    match count {
+        1 => Ok(Some(byte)),
+        0 => Ok(None),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Zero-width combining marks.
+const ZERO_WIDTH: [(u32, u32); 10] = [
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x0591, 0x05bd),
+    (0x0610, 0x061a),
+    (0x064b, 0x065f),
+    (0x1ab0, 0x1aff),
+    (0x1dc0, 0x1dff),
+    (0x200b, 0x200f),
+    (0x20d0, 0x20ff),
+    (0xfe00, 0xfe0f),
+];
+
+/// East Asian wide and fullwidth characters, and emoji.
+const DOUBLE_WIDTH: [(u32, u32); 16] = [
+    (0x1100, 0x115f),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+    (0x1f680, 0x1f6ff),
+];
+
+/// The number of terminal columns `c` occupies.
+pub fn char_width(c: char) -> usize {
+    let code = c as u32;
+    let in_table = |table: &[(u32, u32)]| {
+        table
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&code))
+    };
+
+    if code < 0x20 || (0x7f..0xa0).contains(&code) || in_table(&ZERO_WIDTH) {
+        0
+    } else if in_table(&DOUBLE_WIDTH) {
+        2
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_width_of_wide_and_combining_characters() {
+        let input = "a日本e\u{301}";
+        let expected = 6;
+
+        let result: usize = input.chars().map(char_width).sum();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_escape_sequence_key_for_ctrl_arrows() {
+        assert_eq!(escape_sequence_key("1;5", b'C'), Key::WordRight);
+        assert_eq!(escape_sequence_key("1;5", b'D'), Key::WordLeft);
+        assert_eq!(escape_sequence_key("3", b'~'), Key::Delete);
+    }
+}
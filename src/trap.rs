@@ -0,0 +1,232 @@
+use crate::signals;
+use crate::utils::shell_quote;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrapCondition {
+    Exit,
+    Signal(i32),
+    Debug,
+    Err,
+}
+
+impl TrapCondition {
+    pub fn parse(name: &str) -> Option<TrapCondition> {
+        match name.to_ascii_uppercase().as_str() {
+            "EXIT" | "0" => Some(TrapCondition::Exit),
+            "DEBUG" => Some(TrapCondition::Debug),
+            "ERR" => Some(TrapCondition::Err),
+            _ => signals::signal_number(name).map(TrapCondition::Signal),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            TrapCondition::Exit => "EXIT".to_string(),
+            TrapCondition::Signal(signal) => signals::signal_name(*signal),
+            TrapCondition::Debug => "DEBUG".to_string(),
+            TrapCondition::Err => "ERR".to_string(),
+        }
+    }
+}
+
+/// The actions registered with `trap`. Signals only get recorded by their
+/// handler; the shell runs the matching action at its next safe point.
+#[derive(Debug, Default)]
+pub struct Traps {
+    actions: BTreeMap<TrapCondition, String>,
+}
+
+impl Traps {
+    pub fn set(&mut self, condition: TrapCondition, action: &str) {
+        if let TrapCondition::Signal(signal) = condition {
+            if action.is_empty() {
+                signals::ignore_signal(signal);
+            } else {
+                signals::catch_signal(signal);
+            }
+        }
+
+        self.actions.insert(condition, action.to_string());
+    }
+
+    pub fn reset(&mut self, condition: TrapCondition) {
+        if let TrapCondition::Signal(signal) = condition {
+            signals::reset_signal(signal);
+        }
+
+        self.actions.remove(&condition);
+    }
+
+    pub fn action(&self, condition: TrapCondition) -> Option<&str> {
+        self.actions.get(&condition).map(String::as_str)
+    }
+
+    pub fn take(&mut self, condition: TrapCondition) -> Option<String> {
+        self.actions.remove(&condition)
+    }
+
+    pub fn caught_signals(&self) -> Vec<i32> {
+        self.actions
+            .iter()
+            .filter_map(|(condition, action)| match condition {
+                TrapCondition::Signal(signal) if !action.is_empty() => Some(*signal),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn describe(&self, condition: TrapCondition) -> Option<String> {
+        self.action(condition).map(|action| {
+            format!("trap -- {} {}\n", shell_quote(action), condition.name())
+        })
+    }
+}
+
+/// Implements `trap [-p] [[action] condition...]`, returning what should be
+/// printed on success or the error message otherwise. Without an action,
+/// or with `-` or a number as the action, the conditions are reset.
+pub fn trap(args: &[String], traps: &mut Traps) -> Result<String, String> {
+    let mut args = args;
+    let mut print = false;
+    if let Some(first) = args.first() {
+        match first.as_str() {
+            "-p" => {
+                print = true;
+                args = &args[1..];
+            }
+            "--" => args = &args[1..],
+            _ => {}
+        }
+    }
+
+    if print || args.is_empty() {
+        let conditions = if args.is_empty() {
+            traps.actions.keys().copied().collect()
+        } else {
+            parse_conditions(args)?
+        };
+
+        return Ok(conditions
+            .into_iter()
+            .filter_map(|condition| traps.describe(condition))
+            .collect());
+    }
+
+    let action = args[0].as_str();
+    let numeric = !action.is_empty() && action.chars().all(|c| c.is_ascii_digit());
+    if args.len() == 1 || numeric {
+        for condition in parse_conditions(args)? {
+            traps.reset(condition);
+        }
+        return Ok(String::new());
+    }
+
+    let conditions = parse_conditions(&args[1..])?;
+    for condition in conditions {
+        if action == "-" {
+            traps.reset(condition);
+        } else {
+            traps.set(condition, action);
+        }
+    }
+
+    Ok(String::new())
+}
+
+fn parse_conditions(names: &[String]) -> Result<Vec<TrapCondition>, String> {
+    names
+        .iter()
+        .map(|name| {
+            TrapCondition::parse(name)
+                .ok_or_else(|| format!("trap: {name}: invalid signal specification\n"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_condition_names() {
+        assert_eq!(TrapCondition::parse("exit"), Some(TrapCondition::Exit));
+        assert_eq!(TrapCondition::parse("0"), Some(TrapCondition::Exit));
+        assert_eq!(TrapCondition::parse("err"), Some(TrapCondition::Err));
+        let usr1 = Some(TrapCondition::Signal(libc::SIGUSR1));
+        assert_eq!(TrapCondition::parse("USR1"), usr1);
+        assert_eq!(TrapCondition::parse("sigusr1"), usr1);
+        assert_eq!(TrapCondition::parse(&libc::SIGUSR1.to_string()), usr1);
+        assert_eq!(TrapCondition::parse("NOPE"), None);
+        assert_eq!(TrapCondition::parse("99"), None);
+    }
+
+    #[test]
+    fn test_trap_prints_with_p() {
+        let mut traps = Traps::default();
+        trap(&args(&["echo 'bye'", "EXIT"]), &mut traps).unwrap();
+        trap(&args(&["", "DEBUG"]), &mut traps).unwrap();
+
+        let result = trap(&[], &mut traps);
+        let expected = "trap -- 'echo '\\''bye'\\''' EXIT\ntrap -- '' DEBUG\n";
+        assert_eq!(result, Ok(expected.to_string()));
+        let result = trap(&args(&["-p", "debug"]), &mut traps);
+        assert_eq!(result, Ok("trap -- '' DEBUG\n".to_string()));
+    }
+
+    #[test]
+    fn test_trap_resets_with_dash_number_or_no_action() {
+        let mut traps = Traps::default();
+        for condition in ["EXIT", "ERR", "DEBUG"] {
+            trap(&args(&["echo", condition]), &mut traps).unwrap();
+        }
+
+        trap(&args(&["-", "EXIT"]), &mut traps).unwrap();
+        trap(&args(&["ERR"]), &mut traps).unwrap();
+        trap(&args(&["0", "DEBUG"]), &mut traps).unwrap();
+        assert_eq!(trap(&[], &mut traps), Ok(String::new()));
+    }
+
+    #[test]
+    fn test_trap_rejects_invalid_conditions() {
+        let mut traps = Traps::default();
+
+        let result = trap(&args(&["echo", "NOPE"]), &mut traps);
+        assert_eq!(result, Err("trap: NOPE: invalid signal specification\n".to_string()));
+        let result = trap(&args(&["echo"]), &mut traps);
+        assert_eq!(result, Err("trap: echo: invalid signal specification\n".to_string()));
+    }
+
+    /// Whether `signal` is ignored once a forked child that set `trapped`
+    /// restores the default dispositions, as it does before an exec.
+    fn ignored_after_restore(trapped: &[(i32, &str)], signal: i32) -> bool {
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            let mut traps = Traps::default();
+            for (trapped, action) in trapped {
+                traps.set(TrapCondition::Signal(*trapped), action);
+            }
+            signals::restore_default_signals().unwrap();
+
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            unsafe { libc::sigaction(signal, std::ptr::null(), &mut action) };
+            unsafe { libc::_exit(i32::from(action.sa_sigaction == libc::SIG_IGN)) };
+        }
+
+        let mut status = 0;
+        unsafe { libc::waitpid(pid, &mut status, 0) };
+        libc::WEXITSTATUS(status) == 1
+    }
+
+    #[test]
+    fn test_children_keep_signals_ignored_by_trap() {
+        assert!(ignored_after_restore(&[(libc::SIGINT, "")], libc::SIGINT));
+        assert!(ignored_after_restore(&[(libc::SIGPIPE, "")], libc::SIGPIPE));
+        assert!(!ignored_after_restore(&[(libc::SIGINT, "echo")], libc::SIGINT));
+        assert!(!ignored_after_restore(&[], libc::SIGQUIT));
+    }
+}
@@ -1,19 +1,32 @@
 use std::{env, fs};
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
+use std::io;
 use std::io::Write;
-use crate::command::{Redirection, RedirectionKind};
+use std::os::fd::{AsRawFd, FromRawFd, RawFd};
+use crate::command::{OutputChannel, Redirection, RedirectionKind};
 
+/// Expands a leading `~` to `HOME`, leaving it as is if `HOME` isn't set.
 pub fn expand_home_path(path: &str) -> String {
-    if path.as_bytes().get(0) == Some(&b'~') {
-        let home_dir = env::var("HOME").unwrap();
+    home_path(path).unwrap_or_else(|| path.to_string())
+}
 
-        let mut expanded_path = path.to_string();
-        expanded_path.remove(0);
-        expanded_path.insert_str(0, home_dir.as_str());
+/// Expands a leading `~` to `HOME`, or returns `None` if `HOME` isn't set.
+pub fn home_path(path: &str) -> Option<String> {
+    match path.strip_prefix('~') {
+        Some(rest) => env::var("HOME").ok().map(|home_dir| format!("{home_dir}{rest}")),
+        None => Some(path.to_string()),
+    }
+}
 
-        expanded_path
-    } else {
-        path.to_string()
+/// Shows a path under the home directory with a leading `~`.
+pub fn abbreviate_home(directory: &str, home: Option<&str>) -> String {
+    match home.filter(|home| !home.is_empty() && *home != "/") {
+        Some(home) if directory == home => "~".to_string(),
+        Some(home) => match directory.strip_prefix(home) {
+            Some(rest) if rest.starts_with('/') => format!("~{rest}"),
+            _ => directory.to_string(),
+        },
+        None => directory.to_string(),
     }
 }
 
@@ -22,6 +35,18 @@ pub fn write_or_append_to_file(message: &str, redirection: &Redirection) {
         RedirectionKind::Redirect => {
             fs::write(&redirection.file, message).unwrap();
         }
+        RedirectionKind::NoClobber => match open_without_clobbering(&redirection.file) {
+            Ok(mut file) => {
+                file.write_all(message.as_bytes()).ok();
+            }
+            Err(e) => eprintln!("{}: {}", redirection.file, error_message(&e)),
+        },
+        RedirectionKind::Duplicate => {
+            io::stdout().flush().ok();
+            if let Ok(mut file) = open_redirection_file(redirection) {
+                file.write_all(message.as_bytes()).ok();
+            }
+        }
         RedirectionKind::Append => {
             let mut file = OpenOptions::new()
                 .create(true)
@@ -35,10 +60,164 @@ pub fn write_or_append_to_file(message: &str, redirection: &Redirection) {
 }
 
 pub fn ensure_file_exists_for_redirection(redirection: &Redirection) {
-    if redirection.kind == RedirectionKind::Redirect
-        || (redirection.kind == RedirectionKind::Append
-        && fs::metadata(&redirection.file).is_err())
-    {
-        fs::write(&redirection.file, String::new()).unwrap();
+    match redirection.kind {
+        RedirectionKind::Duplicate => {}
+        RedirectionKind::Append if fs::metadata(&redirection.file).is_ok() => {}
+        // Creates the file if it is missing, without touching one that isn't.
+        RedirectionKind::NoClobber => {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&redirection.file)
+                .ok();
+        }
+        _ => fs::write(&redirection.file, String::new()).unwrap(),
+    }
+}
+
+pub fn open_redirection_file(redirection: &Redirection) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    match redirection.kind {
+        RedirectionKind::Redirect => options.write(true).truncate(true),
+        RedirectionKind::NoClobber => return open_without_clobbering(&redirection.file),
+        RedirectionKind::Append => options.append(true),
+        RedirectionKind::Duplicate => return duplicate_descriptor(&redirection.file),
+    };
+
+    options.open(&redirection.file)
+}
+
+/// Opens `file` for `>` under `set -o noclobber`: a new file is created,
+/// and an existing one is only opened if it isn't regular, such as
+/// `/dev/null`. The file is checked once it is open, without truncating
+/// it, so one created in the meantime isn't overwritten either.
+pub fn open_without_clobbering(file: &str) -> io::Result<File> {
+    match OpenOptions::new().write(true).create_new(true).open(file) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        result => return result,
+    }
+
+    let opened = OpenOptions::new().write(true).open(file)?;
+    match opened.metadata()?.is_file() {
+        true => Err(clobber_error()),
+        false => Ok(opened),
+    }
+}
+
+/// Fails if the redirection can't be made: `>` to an existing regular file
+/// under `set -o noclobber`, or `>&N` when descriptor N isn't open. Devices
+/// such as `/dev/null` may still be written.
+pub fn check_redirection(redirection: &Redirection) -> io::Result<()> {
+    match redirection.kind {
+        RedirectionKind::NoClobber => refuse_to_clobber(&redirection.file),
+        RedirectionKind::Duplicate => duplicate_descriptor(&redirection.file).map(drop),
+        _ => Ok(()),
+    }
+}
+
+/// Opens a new descriptor for the shell's descriptor `fd`, sharing its
+/// file offset.
+fn duplicate_descriptor(fd: &str) -> io::Result<File> {
+    let fd: RawFd = fd
+        .parse()
+        .map_err(|_| io::Error::from_raw_os_error(libc::EBADF))?;
+    let duplicate = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+    if duplicate < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { File::from_raw_fd(duplicate) })
+}
+
+/// Fails if `file` is an existing regular file, which `noclobber` protects.
+pub fn refuse_to_clobber(file: &str) -> io::Result<()> {
+    match fs::metadata(file) {
+        Ok(metadata) if metadata.is_file() => Err(clobber_error()),
+        _ => Ok(()),
+    }
+}
+
+fn clobber_error() -> io::Error {
+    io::Error::new(io::ErrorKind::AlreadyExists, "cannot overwrite existing file")
+}
+
+/// Quotes `value` so that the shell reads it back as a single word.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Quotes `value` like `shell_quote`, but only if it isn't already read
+/// back as a single word by itself.
+pub fn quote_if_needed(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.chars().all(|c| {
+            c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ',' | ':' | '+' | '@' | '%' | '=')
+        });
+    match plain {
+        true => value.to_string(),
+        false => shell_quote(value),
+    }
+}
+
+/// The description of an OS error without Rust's "(os error N)" suffix.
+pub fn error_message(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Points the shell's own stdout or stderr at the redirection target while
+/// `f` runs, for builtins such as `source` that run other commands.
+pub fn with_redirection<T>(
+    redirection: &Option<Redirection>,
+    f: impl FnOnce() -> T,
+) -> io::Result<T> {
+    let Some(redirection) = redirection else {
+        return Ok(f());
+    };
+
+    let fd = match redirection.channel {
+        OutputChannel::Stdout => libc::STDOUT_FILENO,
+        OutputChannel::Stderr => libc::STDERR_FILENO,
+    };
+    let file = open_redirection_file(redirection)?;
+    io::stdout().flush()?;
+
+    let saved = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+    if saved < 0 || unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let result = f();
+
+    io::stdout().flush().ok();
+    unsafe {
+        libc::dup2(saved, fd);
+        libc::close(saved);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_without_clobbering() {
+        let path = env::temp_dir().join(format!("noclobber_test_{}", std::process::id()));
+        let file = path.to_str().unwrap();
+
+        open_without_clobbering(file).unwrap().write_all(b"kept").unwrap();
+        let error = open_without_clobbering(file).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "kept");
+        assert!(open_without_clobbering("/dev/null").is_ok());
+
+        fs::remove_file(path).unwrap();
     }
 }
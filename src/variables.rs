@@ -0,0 +1,415 @@
+use crate::arithmetic;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+/// What a variable holds: a string, or an indexed or associative array.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Scalar(String),
+    /// Indexed arrays may be sparse, so they map indices to elements.
+    Indexed(BTreeMap<usize, String>),
+    Associative(BTreeMap<String, String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: Value,
+    pub exported: bool,
+    pub readonly: bool,
+    /// Set by `declare -i`: assigned values are evaluated arithmetically.
+    pub integer: bool,
+}
+
+impl Variable {
+    fn new(value: Value) -> Self {
+        Variable {
+            value,
+            exported: false,
+            readonly: false,
+            integer: false,
+        }
+    }
+
+    /// The value as a string. As in bash, an array stands for its element
+    /// zero.
+    pub fn scalar(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar(value) => Some(value),
+            Value::Indexed(elements) => elements.get(&0).map(String::as_str),
+            Value::Associative(elements) => elements.get("0").map(String::as_str),
+        }
+    }
+
+    /// The elements in index order; a string is a one-element array.
+    pub fn elements(&self) -> Vec<String> {
+        match &self.value {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Indexed(elements) => elements.values().cloned().collect(),
+            Value::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    /// The indices or keys of the elements.
+    pub fn keys(&self) -> Vec<String> {
+        match &self.value {
+            Value::Scalar(_) => vec!["0".to_string()],
+            Value::Indexed(elements) => elements.keys().map(usize::to_string).collect(),
+            Value::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+}
+
+/// The value on the right of `name=value`, `name=(...)` or `name+=...`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AssignedValue {
+    Scalar(String),
+    /// The elements of `(...)`, each with its `[subscript]=` if it had one.
+    Compound(Vec<(Option<String>, String)>),
+}
+
+/// An expanded assignment, ready to be made.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Assignment {
+    pub name: String,
+    /// The subscript of `name[subscript]=value`.
+    pub index: Option<String>,
+    /// Whether this is `+=`, which appends to strings and arrays.
+    pub append: bool,
+    pub value: AssignedValue,
+}
+
+impl Assignment {
+    pub fn scalar(name: &str, value: &str) -> Self {
+        Assignment {
+            name: name.to_string(),
+            index: None,
+            append: false,
+            value: AssignedValue::Scalar(value.to_string()),
+        }
+    }
+
+    pub fn array(name: &str, elements: &[String]) -> Self {
+        Assignment {
+            name: name.to_string(),
+            index: None,
+            append: false,
+            value: AssignedValue::Compound(
+                elements.iter().map(|element| (None, element.clone())).collect(),
+            ),
+        }
+    }
+}
+
+/// Shell variables. Exported ones are mirrored into the process environment
+/// so that children and `env::var` callers see them.
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    map: HashMap<String, Variable>,
+}
+
+impl Variables {
+    pub fn from_environment() -> Self {
+        let map = env::vars()
+            .map(|(name, value)| {
+                let mut variable = Variable::new(Value::Scalar(value));
+                variable.exported = true;
+                (name, variable)
+            })
+            .collect();
+
+        Variables { map }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name).and_then(Variable::scalar)
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.map.get(name)
+    }
+
+    /// The variable called `name`, created empty if it doesn't exist.
+    pub fn variable_mut(&mut self, name: &str) -> &mut Variable {
+        self.map
+            .entry(name.to_string())
+            .or_insert_with(|| Variable::new(Value::Scalar(String::new())))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+
+    /// The elements of an array, or the value of a string as one element.
+    pub fn elements(&self, name: &str) -> Vec<String> {
+        self.map.get(name).map(Variable::elements).unwrap_or_default()
+    }
+
+    /// The element `name[subscript]`. Subscripts of indexed arrays are
+    /// arithmetic expressions, and negative ones count from the end.
+    pub fn element(&self, name: &str, subscript: &str) -> Option<String> {
+        let variable = self.map.get(name)?;
+        match &variable.value {
+            Value::Associative(elements) => elements.get(subscript).cloned(),
+            Value::Indexed(elements) => {
+                let index = self.resolve_index(elements, subscript).ok()?;
+                elements.get(&index).cloned()
+            }
+            Value::Scalar(value) => {
+                let index = arithmetic::evaluate(subscript, self).ok()?;
+                (index == 0 || index == -1).then(|| value.clone())
+            }
+        }
+    }
+
+    fn resolve_index(
+        &self,
+        elements: &BTreeMap<usize, String>,
+        subscript: &str,
+    ) -> Result<usize, String> {
+        let index = arithmetic::evaluate(subscript, self)?;
+        if index >= 0 {
+            return Ok(index as usize);
+        }
+
+        let end = elements.keys().next_back().map_or(0, |last| last + 1) as i64;
+        usize::try_from(end + index).map_err(|_| format!("{subscript}: bad array subscript"))
+    }
+
+    /// Sets a variable without checking its attributes, for values the
+    /// shell maintains itself. Setting an array sets its element zero.
+    pub fn set(&mut self, name: &str, value: &str) {
+        let variable = self.variable_mut(name);
+        match &mut variable.value {
+            Value::Scalar(current) => *current = value.to_string(),
+            Value::Indexed(elements) => {
+                elements.insert(0, value.to_string());
+            }
+            Value::Associative(elements) => {
+                elements.insert("0".to_string(), value.to_string());
+            }
+        }
+
+        if variable.exported {
+            env::set_var(name, value);
+        }
+    }
+
+    /// Sets a variable and marks it for export to children.
+    pub fn set_exported(&mut self, name: &str, value: &str) {
+        let variable = self.variable_mut(name);
+        variable.value = Value::Scalar(value.to_string());
+        variable.exported = true;
+        env::set_var(name, value);
+    }
+
+    /// Marks a variable for export to children or stops exporting it.
+    /// Arrays stay out of the environment.
+    pub fn set_export(&mut self, name: &str, exported: bool) {
+        let variable = self.variable_mut(name);
+        variable.exported = exported;
+        match &variable.value {
+            Value::Scalar(value) if exported => env::set_var(name, value),
+            _ => env::remove_var(name),
+        }
+    }
+
+    /// Replaces a variable with an indexed array of `elements`.
+    pub fn set_array(&mut self, name: &str, elements: &[String]) {
+        self.variable_mut(name).value = Value::Indexed(elements.iter().cloned().enumerate().collect());
+    }
+
+    /// Makes an assignment written in a command, refusing to change readonly
+    /// variables and evaluating values of integer ones.
+    pub fn assign(&mut self, assignment: &Assignment) -> Result<(), String> {
+        let name = assignment.name.as_str();
+        let (readonly, integer) = self
+            .map
+            .get(name)
+            .map_or((false, false), |variable| (variable.readonly, variable.integer));
+        if readonly {
+            return Err(format!("{name}: readonly variable"));
+        }
+
+        match (&assignment.index, &assignment.value) {
+            (None, AssignedValue::Scalar(value)) => {
+                let current = self.get(name).unwrap_or_default().to_string();
+                let value = self.combine(&current, value, assignment.append, integer)?;
+                self.set(name, &value);
+                Ok(())
+            }
+            (Some(subscript), AssignedValue::Scalar(value)) => {
+                self.assign_element(name, subscript, value, assignment.append, integer)
+            }
+            (None, AssignedValue::Compound(elements)) => {
+                self.assign_compound(name, elements, assignment.append, integer)
+            }
+            (Some(subscript), AssignedValue::Compound(_)) => Err(format!(
+                "{name}[{subscript}]: cannot assign list to array member"
+            )),
+        }
+    }
+
+    /// The value to store for `value` assigned to a variable holding
+    /// `current`: appended for `+=`, and evaluated for integer variables.
+    fn combine(&self, current: &str, value: &str, append: bool, integer: bool) -> Result<String, String> {
+        match (integer, append) {
+            (true, true) => {
+                let current = arithmetic::evaluate(current, self)?;
+                Ok(current.wrapping_add(arithmetic::evaluate(value, self)?).to_string())
+            }
+            (true, false) => Ok(arithmetic::evaluate(value, self)?.to_string()),
+            (false, true) => Ok(format!("{current}{value}")),
+            (false, false) => Ok(value.to_string()),
+        }
+    }
+
+    fn assign_element(
+        &mut self,
+        name: &str,
+        subscript: &str,
+        value: &str,
+        append: bool,
+        integer: bool,
+    ) -> Result<(), String> {
+        let current = self.map.get(name).map(|variable| variable.value.clone());
+        match current {
+            Some(Value::Associative(mut elements)) => {
+                let existing = elements.get(subscript).cloned().unwrap_or_default();
+                let value = self.combine(&existing, value, append, integer)?;
+                elements.insert(subscript.to_string(), value);
+                self.variable_mut(name).value = Value::Associative(elements);
+            }
+            current => {
+                let mut elements = match current {
+                    Some(Value::Indexed(elements)) => elements,
+                    Some(Value::Scalar(value)) => BTreeMap::from([(0, value)]),
+                    _ => BTreeMap::new(),
+                };
+                let index = self.resolve_index(&elements, subscript)?;
+                let existing = elements.get(&index).cloned().unwrap_or_default();
+                let value = self.combine(&existing, value, append, integer)?;
+                elements.insert(index, value);
+                self.variable_mut(name).value = Value::Indexed(elements);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn assign_compound(
+        &mut self,
+        name: &str,
+        assigned: &[(Option<String>, String)],
+        append: bool,
+        integer: bool,
+    ) -> Result<(), String> {
+        let current = self.map.get(name).map(|variable| variable.value.clone());
+        let value = match current {
+            Some(Value::Associative(existing)) => {
+                let mut elements = if append { existing } else { BTreeMap::new() };
+                for (key, value) in assigned {
+                    let Some(key) = key else {
+                        return Err(format!(
+                            "{name}: {value}: must use subscript when assigning associative array"
+                        ));
+                    };
+                    elements.insert(key.clone(), self.combine("", value, false, integer)?);
+                }
+                Value::Associative(elements)
+            }
+            current => {
+                let mut elements = match current {
+                    Some(Value::Indexed(existing)) if append => existing,
+                    Some(Value::Scalar(existing)) if append => BTreeMap::from([(0, existing)]),
+                    _ => BTreeMap::new(),
+                };
+                let mut next = elements.keys().next_back().map_or(0, |last| last + 1);
+                for (key, value) in assigned {
+                    if let Some(key) = key {
+                        next = self.resolve_index(&elements, key)?;
+                    }
+                    elements.insert(next, self.combine("", value, false, integer)?);
+                    next += 1;
+                }
+                Value::Indexed(elements)
+            }
+        };
+
+        self.variable_mut(name).value = value;
+        Ok(())
+    }
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// Splits `NAME=value` into its parts if the word is an assignment.
+pub fn parse_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    is_valid_name(name).then_some((name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(elements: &[(Option<&str>, &str)]) -> AssignedValue {
+        AssignedValue::Compound(
+            elements
+                .iter()
+                .map(|(key, value)| (key.map(str::to_string), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_assign_indexed_arrays() {
+        let mut variables = Variables::default();
+        let mut assignment = Assignment {
+            name: "a".to_string(),
+            index: None,
+            append: false,
+            value: compound(&[(None, "x"), (Some("5"), "y"), (None, "z")]),
+        };
+        variables.assign(&assignment).unwrap();
+        assignment.append = true;
+        assignment.value = compound(&[(None, "w")]);
+        variables.assign(&assignment).unwrap();
+
+        assert_eq!(variables.elements("a"), vec!["x", "y", "z", "w"]);
+        assert_eq!(variables.element("a", "6"), Some("z".to_string()));
+        assert_eq!(variables.element("a", "-1"), Some("w".to_string()));
+        assert_eq!(variables.get("a"), Some("x"));
+    }
+
+    #[test]
+    fn test_assign_enforces_readonly_and_integer() {
+        let mut variables = Variables::default();
+        variables.variable_mut("n").integer = true;
+        variables.assign(&Assignment::scalar("n", "6 * 7")).unwrap();
+        let mut append = Assignment::scalar("n", "8");
+        append.append = true;
+        variables.assign(&append).unwrap();
+        assert_eq!(variables.get("n"), Some("50"));
+
+        variables.variable_mut("n").readonly = true;
+        let result = variables.assign(&Assignment::scalar("n", "1"));
+        assert_eq!(result, Err("n: readonly variable".to_string()));
+    }
+
+    #[test]
+    fn test_assign_associative_requires_subscripts() {
+        let mut variables = Variables::default();
+        variables.variable_mut("m").value = Value::Associative(BTreeMap::new());
+        let mut assignment = Assignment::scalar("m", "");
+        assignment.value = compound(&[(Some("k"), "v")]);
+        variables.assign(&assignment).unwrap();
+
+        assert_eq!(variables.element("m", "k"), Some("v".to_string()));
+        assignment.value = compound(&[(None, "v")]);
+        assert!(variables.assign(&assignment).is_err());
+    }
+}